@@ -7,7 +7,6 @@ use telebot::RcBot;
 use tokio_core::reactor::Core;
 use futures::stream::Stream;
 use std::env;
-use futures::IntoFuture;
 
 use erased_serde::Serialize;
 
@@ -21,8 +20,7 @@ fn main() {
     // Create the bot
     let bot = RcBot::new(lp.handle(), &env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
 
-    let stream = bot.get_stream()
-        .filter_map(|(bot, msg)| msg.inline_query.map(|query| (bot, query)))
+    let stream = bot.inline_queries()
         .and_then(|(bot, query)| {
             let result: Vec<Box<Serialize>> = vec![
                 Box::new(
@@ -43,6 +41,8 @@ fn main() {
                 .send()
         });
 
+    bot.register(stream);
+
     // enter the main loop
-    lp.run(stream.for_each(|_| Ok(())).into_future()).unwrap();
+    bot.run(&mut lp).unwrap();
 }
@@ -0,0 +1,64 @@
+extern crate futures;
+extern crate telebot;
+#[macro_use]
+extern crate telebot_derive;
+extern crate tokio_core;
+
+use telebot::RcBot;
+use telebot::command::BotCommands;
+use tokio_core::reactor::Core;
+use futures::stream::Stream;
+use futures::Future;
+use std::env;
+
+// import all available functions
+use telebot::functions::*;
+
+/// Available commands
+#[derive(BotCommands)]
+enum Cmd {
+    /// Sends a point on the map, e.g. /location 2.321 12.32
+    Location { latitude: f32, longitude: f32 },
+    /// Repeats a text a number of times
+    Repeat {
+        times: u8,
+        #[rest]
+        text: String,
+    },
+    /// Shows this help
+    Help,
+}
+
+fn main() {
+    // Create a new tokio core
+    let mut lp = Core::new().unwrap();
+
+    // Create the bot
+    let bot = RcBot::new(lp.handle(), &env::var("TELEGRAM_BOT_KEY").unwrap()).update_interval(200);
+
+    // Every command of the enum is registered, the arguments are already parsed
+    let handle = bot.typed_cmds::<Cmd>()
+        .and_then(|(bot, msg, cmd)| {
+            let chat_id = msg.chat.id;
+
+            match cmd {
+                Ok(Cmd::Location {
+                    latitude,
+                    longitude,
+                }) => Box::new(bot.location(chat_id, latitude, longitude).send())
+                    as Box<Future<Item = _, Error = _>>,
+                Ok(Cmd::Repeat { times, text }) => {
+                    let text = vec![text; times as usize].join("\n");
+
+                    Box::new(bot.message(chat_id, text).send())
+                }
+                Ok(Cmd::Help) => Box::new(bot.message(chat_id, Cmd::help()).send()),
+                Err(usage) => Box::new(bot.message(chat_id, usage.to_string()).send()),
+            }
+        });
+
+    bot.register(handle);
+
+    // Enter the main loop
+    bot.run(&mut lp).unwrap();
+}
@@ -2,19 +2,37 @@
 //! as an underlying field. You should always use RcBot.
 
 use objects;
-use functions::FunctionGetMe;
+use command::{BotCommands, Command, CommandOptions, UpdateKind};
+use functions::{FunctionGetMe, FunctionMessage, FunctionSetMyCommands};
 use failure::{Error, Fail, ResultExt};
-use error::{ErrorKind, TelegramError};
+use error::{ErrorKind, ErrorOrigin, HandlerError, TelegramError, UsageError};
 use file::File;
+use text::{Captures, Regex};
+use filter::{self, Filter};
+use middleware::{Extensions, Middleware};
+use dialogue::{Dialogue, EXPIRE_INTERVAL};
+use deeplink;
+use scheduler::{Clock, JobId, Schedule, Scheduler};
+use scheduled::{CallStore, MemoryCallStore, ScheduledCall};
+use session::{MemoryStore, Session, SessionKey, SessionStore};
+use event::{ChatEvent, EventKind};
+use handler::{self, HandlerStream};
+use queue::{ChatItem, ChatQueue};
+use wait::Waiters;
 
-use std::{str, time::Duration, collections::HashMap, rc::Rc, cell::{Cell, RefCell}};
+use std::{mem, str, hash::Hash, io::{Cursor, Read}, time::Duration, collections::HashMap, rc::Rc,
+          cell::{Cell, RefCell}};
 
-use tokio_core::reactor::{Core, Handle, Interval};
+use tokio_core::reactor::{Core, Handle, Interval, Timeout};
 use hyper::{Body, Client, Request, Uri, header::CONTENT_TYPE, client::{HttpConnector, ResponseFuture}};
 use hyper_tls::HttpsConnector;
+use chrono::{DateTime, Utc};
 use hyper_multipart::client::multipart;
 use serde_json::{self, value::Value};
-use futures::{stream, Future, IntoFuture, Stream, sync::mpsc::{self, UnboundedSender}};
+use futures::{future, stream, Future, IntoFuture, Stream, sync::mpsc::{self, UnboundedSender}};
+
+/// The seconds until a scheduled call which failed for a transient reason is sent again
+const CALL_RETRY_DELAY: i64 = 60;
 
 /// A clonable, single threaded bot
 ///
@@ -32,6 +50,48 @@ impl RcBot {
     }
 }
 
+/// Takes new messages out of the normal dispatch before they are checked for commands, e.g. to
+/// pass them to a running conversation
+pub trait Interceptor {
+    /// Returns the message if it wasn't taken
+    fn intercept(&self, bot: &RcBot, msg: objects::Message) -> Option<objects::Message>;
+
+    /// Moves the data of a group to the id of the supergroup it was upgraded to
+    fn migrate_chat(&self, _from: objects::Integer, _to: objects::Integer) {}
+}
+
+/// Receives every error of a handler together with the origin of the failed item
+pub type ErrorHook = Rc<Fn(&RcBot, &HandlerError)>;
+
+/// A registered command together with its options
+pub struct CommandHandler {
+    pub id: usize,
+    pub options: CommandOptions,
+    pub sender: UnboundedSender<(RcBot, objects::Message, Command)>,
+}
+
+/// A registered text handler together with its expression
+pub struct TextHandler {
+    pub id: usize,
+    pub regex: Regex,
+    pub filter: Option<Rc<Filter>>,
+    pub sender: UnboundedSender<(RcBot, objects::Message, Captures)>,
+}
+
+/// A registered callback handler together with its filter
+pub struct CallbackHandler {
+    pub id: usize,
+    pub filter: Option<Rc<Filter>>,
+    pub sender: UnboundedSender<(RcBot, objects::CallbackQuery)>,
+}
+
+/// A registered handler of service events together with the kinds it receives
+pub struct EventHandler {
+    pub id: usize,
+    pub kinds: Vec<EventKind>,
+    pub sender: UnboundedSender<(RcBot, objects::Message, ChatEvent)>,
+}
+
 /// The main bot structure
 pub struct Bot {
     pub key: String,
@@ -40,14 +100,39 @@ pub struct Bot {
     pub last_id: Cell<u32>,
     pub update_interval: Cell<u64>,
     pub timeout: Cell<u64>,
-    pub handlers: RefCell<HashMap<String, UnboundedSender<(RcBot, objects::Message)>>>,
+    pub next_handler_id: Cell<usize>,
+    pub chat_queue: ChatQueue,
+    pub scheduler: Scheduler,
+    pub call_store: RefCell<Rc<CallStore>>,
+    pub call_jobs: RefCell<HashMap<String, JobId>>,
+    pub error_hook: RefCell<Option<ErrorHook>>,
+    pub error_reply: RefCell<Option<String>>,
+    pub auto_help: Cell<bool>,
+    pub middlewares: RefCell<Vec<Rc<Middleware>>>,
+    pub session_store: RefCell<Rc<SessionStore>>,
+    pub interceptors: RefCell<Vec<Rc<Interceptor>>>,
+    pub waiters: Waiters,
+    pub handlers: RefCell<HashMap<String, Vec<CommandHandler>>>,
+    pub text_handlers: RefCell<Vec<TextHandler>>,
     pub unknown_handler: RefCell<Option<UnboundedSender<(RcBot, objects::Message)>>>,
+    pub edited_message_handler: RefCell<Option<UnboundedSender<(RcBot, objects::Message)>>>,
+    pub channel_post_handler: RefCell<Option<UnboundedSender<(RcBot, objects::Message)>>>,
+    pub edited_channel_post_handler: RefCell<Option<UnboundedSender<(RcBot, objects::Message)>>>,
+    pub callback_handlers: RefCell<Vec<CallbackHandler>>,
+    pub event_handlers: RefCell<Vec<EventHandler>>,
+    pub inline_query_handler: RefCell<Option<UnboundedSender<(RcBot, objects::InlineQuery)>>>,
+    pub chosen_inline_result_handler:
+        RefCell<Option<UnboundedSender<(RcBot, objects::ChosenInlineResult)>>>,
 }
 
 impl Bot {
     pub fn new(handle: Handle, key: &str) -> Bot {
         debug!("Create a new bot with the key {}", key);
 
+        // awaited messages are taken before they reach a dialogue or another interceptor
+        let waiters = Waiters::new();
+        let interceptors: Vec<Rc<Interceptor>> = vec![Rc::new(waiters.clone())];
+
         Bot {
             handle: handle.clone(),
             key: key.into(),
@@ -55,47 +140,83 @@ impl Bot {
             last_id: Cell::new(0),
             update_interval: Cell::new(1000),
             timeout: Cell::new(30),
+            next_handler_id: Cell::new(0),
+            chat_queue: ChatQueue::new(handle.clone()),
+            scheduler: Scheduler::new(),
+            call_store: RefCell::new(Rc::new(MemoryCallStore::new())),
+            call_jobs: RefCell::new(HashMap::new()),
+            error_hook: RefCell::new(None),
+            error_reply: RefCell::new(None),
+            auto_help: Cell::new(true),
+            middlewares: RefCell::new(Vec::new()),
+            session_store: RefCell::new(Rc::new(MemoryStore::new())),
+            interceptors: RefCell::new(interceptors),
+            waiters,
             handlers: RefCell::new(HashMap::new()),
+            text_handlers: RefCell::new(Vec::new()),
             unknown_handler: RefCell::new(None),
+            edited_message_handler: RefCell::new(None),
+            channel_post_handler: RefCell::new(None),
+            edited_channel_post_handler: RefCell::new(None),
+            callback_handlers: RefCell::new(Vec::new()),
+            event_handlers: RefCell::new(Vec::new()),
+            inline_query_handler: RefCell::new(None),
+            chosen_inline_result_handler: RefCell::new(None),
         }
     }
 
-    /// Creates a new request and adds a JSON message to it. The returned Future contains a the
-    /// reply as a string.  This method should be used if no file is added becontext a JSON msg is
-    /// always compacter than a formdata one.
-    pub fn fetch_json(
-        &self,
-        func: &'static str,
-        msg: &str,
-    ) -> impl Future<Item = String, Error = Error> {
-        debug!("Send JSON: {}", msg);
-
-        let request = self.build_json(func, String::from(msg));
+    /// Returns a new id for the registration of a handler
+    pub fn next_handler_id(&self) -> usize {
+        let id = self.next_handler_id.get();
+        self.next_handler_id.set(id + 1);
 
-        request
-            .into_future()
-            .and_then(|(client, request)| _fetch(client.request(request)))
+        id
     }
 
-    /// Builds the HTTP header for a JSON request. The JSON is already converted to a str and is
-    /// appended to the POST header.
-    fn build_json(
-        &self,
-        func: &'static str,
-        msg: String,
-    ) -> Result<(Client<HttpsConnector<HttpConnector>, Body>, Request<Body>), Error> {
-        let url: Result<Uri, _> =
-            format!("https://api.telegram.org/bot{}/{}", self.key, func).parse();
+    /// Removes the command, text or callback handler with the given id. Returns false if no
+    /// handler was registered with the id.
+    pub fn remove_handler(&self, id: usize) -> bool {
+        let mut removed = false;
+
+        {
+            let mut handlers = self.handlers.borrow_mut();
+
+            for cmd_handlers in handlers.values_mut() {
+                let len = cmd_handlers.len();
+                cmd_handlers.retain(|handler| handler.id != id);
+                removed |= cmd_handlers.len() != len;
+            }
+
+            // a command without handlers is unknown again
+            handlers.retain(|_, cmd_handlers| !cmd_handlers.is_empty());
+        }
+
+        let mut text_handlers = self.text_handlers.borrow_mut();
+        let len = text_handlers.len();
+        text_handlers.retain(|handler| handler.id != id);
+        removed |= text_handlers.len() != len;
 
-        let client = Client::builder()
-            .build(HttpsConnector::new(2).context(ErrorKind::HttpsInitializeError)?);
+        let mut callback_handlers = self.callback_handlers.borrow_mut();
+        let len = callback_handlers.len();
+        callback_handlers.retain(|handler| handler.id != id);
+        removed |= callback_handlers.len() != len;
 
-        let req = Request::post(url.context(ErrorKind::Uri)?)
-            .header(CONTENT_TYPE, "application/json")
-            .body(msg.into())
-            .context(ErrorKind::Hyper)?;
+        let mut event_handlers = self.event_handlers.borrow_mut();
+        let len = event_handlers.len();
+        event_handlers.retain(|handler| handler.id != id);
+        removed |= event_handlers.len() != len;
 
-        Ok((client, req))
+        removed
+    }
+
+    /// Creates a new request and adds a JSON message to it. The returned Future contains a the
+    /// reply as a string.  This method should be used if no file is added becontext a JSON msg is
+    /// always compacter than a formdata one.
+    ///
+    /// A request which failed because its group was upgraded to a supergroup is repeated once
+    /// with the new chat id.
+    pub fn fetch_json(&self, func: &str, msg: &str) -> impl Future<Item = String, Error = Error> {
+        request_json(&self.key, func, msg)
     }
 
     /// Creates a new request with some byte content (e.g. a file). The method properties have to be
@@ -107,64 +228,248 @@ impl Bot {
         file: File,
         kind: &str,
     ) -> impl Future<Item = String, Error = Error> {
-        debug!("Send formdata: {}", msg.to_string());
+        request_formdata(&self.key, func, msg, file, kind)
+    }
+}
+
+/// Sends a JSON request with the key of a bot, see `Bot::fetch_json`. The future is `Send`, so it
+/// is shared by `RcBot` and `SyncBot`.
+pub fn request_json(
+    key: &str,
+    func: &str,
+    msg: &str,
+) -> impl Future<Item = String, Error = Error> + Send {
+    debug!("Send JSON: {}", msg);
+
+    let key = String::from(key);
+    let func = String::from(func);
+    let msg = String::from(msg);
+
+    build_json(&key, &func, msg.clone())
+        .into_future()
+        .and_then(|(client, request)| _fetch(client.request(request)))
+        .or_else(move |err| -> Box<Future<Item = String, Error = Error> + Send> {
+            let retry = TelegramError::find(&err)
+                .and_then(TelegramError::migrate_to_chat_id)
+                .and_then(|to| migrate_request(&msg, to));
+
+            match retry {
+                Some(msg) => {
+                    debug!("The chat was migrated, send the request again: {}", msg);
+
+                    Box::new(
+                        build_json(&key, &func, msg)
+                            .into_future()
+                            .and_then(|(client, request)| _fetch(client.request(request))),
+                    )
+                }
+                None => Box::new(future::err(err)),
+            }
+        })
+}
 
-        let request = self.build_formdata(func, msg, file, kind);
+/// Sends a formdata request with the key of a bot, see `Bot::fetch_formdata`. Like JSON requests
+/// it is repeated once with the new chat id if the group was upgraded to a supergroup.
+pub fn request_formdata(
+    key: &str,
+    func: &'static str,
+    msg: &Value,
+    file: File,
+    kind: &str,
+) -> impl Future<Item = String, Error = Error> + Send {
+    debug!("Send formdata: {}", msg.to_string());
+
+    let key = String::from(key);
+    let kind = String::from(kind);
+    let msg = msg.clone();
+
+    future::result(Upload::read(file)).and_then(move |upload| {
+        let request = build_formdata(&key, func, &msg, upload.file(), &kind);
 
         request
             .into_future()
             .and_then(|(client, request)| _fetch(client.request(request)))
-    }
+            .or_else(move |err| -> Box<Future<Item = String, Error = Error> + Send> {
+                let retry = TelegramError::find(&err)
+                    .and_then(TelegramError::migrate_to_chat_id)
+                    .and_then(|to| migrate_params(&msg, to));
 
-    /// Builds the HTTP header for a formdata request. The file content is read and then append to
-    /// the formdata. Each key-value pair has a own line.
-    fn build_formdata(
-        &self,
-        func: &'static str,
-        msg: &Value,
-        file: File,
-        kind: &str,
-    ) -> Result<
-        (
-            Client<HttpsConnector<HttpConnector>, Body>,
-            Request<Body>,
-        ),
-        Error,
-    > {
-        let client: Client<HttpsConnector<_>, Body> = Client::builder()
-            .keep_alive(true)
-            .build(HttpsConnector::new(4).context(ErrorKind::HttpsInitializeError)?);
-
-        let url: Result<Uri, _> =
-            format!("https://api.telegram.org/bot{}/{}", self.key, func).parse();
-
-        let mut req_builder = Request::post(url.context(ErrorKind::Uri)?);
-        let mut form = multipart::Form::default();
-
-        let msg = msg.as_object().ok_or(ErrorKind::JsonNotMap)?;
-
-        // add properties
-        for (key, val) in msg.iter() {
-            let val = match val {
-                &Value::String(ref val) => format!("{}", val),
-                etc => format!("{}", etc),
-            };
+                match retry {
+                    Some(msg) => {
+                        debug!("The chat was migrated, send the request again: {}", msg);
 
-            form.add_text(key, val.as_ref());
-        }
+                        let request = build_formdata(&key, func, &msg, upload.file(), &kind);
+
+                        Box::new(
+                            request
+                                .into_future()
+                                .and_then(|(client, request)| _fetch(client.request(request))),
+                        )
+                    }
+                    None => Box::new(future::err(err)),
+                }
+            })
+    })
+}
+
+/// The file of a formdata request, which can be sent again. A file in memory is read into a
+/// buffer for this.
+enum Upload {
+    Memory { name: String, data: Vec<u8> },
+    Disk { path: String },
+}
 
+impl Upload {
+    fn read(file: File) -> Result<Upload, Error> {
         match file {
-            File::Memory { name, source } => {
-                form.add_reader_file(kind, source, name);
-            }
-            File::Disk { path } => {
-                form.add_file(kind, path).context(ErrorKind::NoFile)?;
+            File::Memory { name, mut source } => {
+                let mut data = Vec::new();
+                source.read_to_end(&mut data).context(ErrorKind::IO)?;
+
+                Ok(Upload::Memory { name, data })
             }
+            File::Disk { path } => Ok(Upload::Disk { path }),
+        }
+    }
+
+    fn file(&self) -> File {
+        match *self {
+            Upload::Memory { ref name, ref data } => File::Memory {
+                name: name.clone(),
+                source: Box::new(Cursor::new(data.clone())),
+            },
+            Upload::Disk { ref path } => File::Disk { path: path.clone() },
+        }
+    }
+}
+
+/// Builds the HTTP header for a formdata request. The file content is read and then append to
+/// the formdata. Each key-value pair has a own line.
+fn build_formdata(
+    key: &str,
+    func: &'static str,
+    msg: &Value,
+    file: File,
+    kind: &str,
+) -> Result<
+    (
+        Client<HttpsConnector<HttpConnector>, Body>,
+        Request<Body>,
+    ),
+    Error,
+> {
+    let client: Client<HttpsConnector<_>, Body> = Client::builder()
+        .keep_alive(true)
+        .build(HttpsConnector::new(4).context(ErrorKind::HttpsInitializeError)?);
+
+    let url: Result<Uri, _> = format!("https://api.telegram.org/bot{}/{}", key, func).parse();
+
+    let mut req_builder = Request::post(url.context(ErrorKind::Uri)?);
+    let mut form = multipart::Form::default();
+
+    let msg = msg.as_object().ok_or(ErrorKind::JsonNotMap)?;
+
+    // add properties
+    for (key, val) in msg.iter() {
+        let val = match val {
+            &Value::String(ref val) => format!("{}", val),
+            etc => format!("{}", etc),
+        };
+
+        form.add_text(key, val.as_ref());
+    }
+
+    match file {
+        File::Memory { name, source } => {
+            form.add_reader_file(kind, source, name);
         }
+        File::Disk { path } => {
+            form.add_file(kind, path).context(ErrorKind::NoFile)?;
+        }
+    }
+
+    let req = form.set_body(&mut req_builder).context(ErrorKind::Hyper)?;
+
+    Ok((client, req))
+}
+
+/// Builds the HTTP header for a JSON request. The JSON is already converted to a str and is
+/// appended to the POST header.
+fn build_json(
+    key: &str,
+    func: &str,
+    msg: String,
+) -> Result<(Client<HttpsConnector<HttpConnector>, Body>, Request<Body>), Error> {
+    let url: Result<Uri, _> = format!("https://api.telegram.org/bot{}/{}", key, func).parse();
+
+    let client = Client::builder()
+        .build(HttpsConnector::new(2).context(ErrorKind::HttpsInitializeError)?);
+
+    let req = Request::post(url.context(ErrorKind::Uri)?)
+        .header(CONTENT_TYPE, "application/json")
+        .body(msg.into())
+        .context(ErrorKind::Hyper)?;
+
+    Ok((client, req))
+}
+
+/// The seconds until a failed scheduled call is sent again, or None if it would fail again
+fn call_retry_delay(error: &Error) -> Option<i64> {
+    match TelegramError::find(error) {
+        Some(e) => match (e.retry_after(), e.code()) {
+            (Some(after), _) => Some(after),
+            (None, Some(code)) if code >= 500 => Some(CALL_RETRY_DELAY),
+            _ => None,
+        },
+        // the request didn't reach Telegram or the answer was broken
+        None => Some(CALL_RETRY_DELAY),
+    }
+}
+
+/// Replaces the chat id of a JSON request with the id of the supergroup, returns None if the
+/// request has no chat id
+fn migrate_request(msg: &str, to: objects::Integer) -> Option<String> {
+    let value = serde_json::from_str::<Value>(msg).ok()?;
 
-        let req = form.set_body(&mut req_builder).context(ErrorKind::Hyper)?;
+    serde_json::to_string(&migrate_params(&value, to)?).ok()
+}
+
+/// Replaces the chat id of the parameters of a request, returns None if they have no chat id
+fn migrate_params(msg: &Value, to: objects::Integer) -> Option<Value> {
+    let mut value = msg.clone();
+
+    match value.get_mut("chat_id") {
+        Some(chat_id) => *chat_id = Value::from(to),
+        None => return None,
+    }
+
+    Some(value)
+}
+
+/// Moves the extensions of an update to the part which is passed to the handlers
+fn move_extensions(val: &mut objects::Update) {
+    if val.extensions.is_empty() {
+        return;
+    }
 
-        Ok((client, req))
+    let extensions = mem::replace(&mut val.extensions, Extensions::new());
+
+    if let Some(ref mut msg) = val.message {
+        msg.extensions = extensions;
+    } else if let Some(ref mut msg) = val.edited_message {
+        msg.extensions = extensions;
+    } else if let Some(ref mut msg) = val.channel_post {
+        msg.extensions = extensions;
+    } else if let Some(ref mut msg) = val.edited_channel_post {
+        msg.extensions = extensions;
+    } else if let Some(ref mut query) = val.callback_query {
+        query.extensions = extensions;
+    } else if let Some(ref mut query) = val.inline_query {
+        query.extensions = extensions;
+    } else if let Some(ref mut result) = val.chosen_inline_result {
+        result.extensions = extensions;
+    } else {
+        val.extensions = extensions;
     }
 }
 
@@ -192,10 +497,16 @@ pub fn _fetch(fut_res: ResponseFuture) -> impl Future<Item = String, Error = Err
                 }
             }
 
+            let parameters = req.get("parameters")
+                .and_then(|parameters| serde_json::from_value(parameters.clone()).ok());
+            let code = req.get("error_code").and_then(Value::as_i64);
+
             let e = match req.get("description").and_then(Value::as_str) {
-                Some(err) => {
-                    Error::from(TelegramError::new(err.into()).context(ErrorKind::Telegram))
-                }
+                Some(err) => Error::from(
+                    TelegramError::with_parameters(err.into(), parameters)
+                        .with_code(code)
+                        .context(ErrorKind::Telegram),
+                ),
                 None => Error::from(ErrorKind::Telegram),
             };
 
@@ -218,58 +529,989 @@ impl RcBot {
         self
     }
 
+    /// Limits the number of handler futures of `register_per_chat` which run at the same time. A
+    /// limit of zero is raised to one.
+    pub fn max_parallel(self, limit: usize) -> RcBot {
+        self.inner.chat_queue.set_limit(Some(limit));
+
+        self
+    }
+
+    /// Sets the clock of the scheduler, e.g. a `ManualClock` to test schedules
+    pub fn clock<C>(self, clock: Rc<C>) -> RcBot
+    where
+        C: Clock + 'static,
+    {
+        self.inner.scheduler.set_clock(clock);
+
+        self
+    }
+
+    /// Sets a hook which receives every error of a registered handler. Without a hook the errors
+    /// are logged.
+    pub fn on_error<F>(self, hook: F) -> RcBot
+    where
+        F: Fn(&RcBot, &HandlerError) + 'static,
+    {
+        *self.inner.error_hook.borrow_mut() = Some(Rc::new(hook));
+
+        self
+    }
+
+    /// Sets a text which is sent to the chat of an item whose handler failed, e.g. "Sorry,
+    /// something went wrong"
+    pub fn error_reply(self, text: &str) -> RcBot {
+        *self.inner.error_reply.borrow_mut() = Some(text.into());
+
+        self
+    }
+
+    /// Passes the error of a handler to the error hook and sends the error reply. An error which
+    /// is already a `HandlerError` keeps its own origin.
+    pub fn report_error(&self, error: Error, origin: ErrorOrigin) {
+        let error = match error.downcast::<HandlerError>() {
+            Ok(error) => error,
+            Err(error) => HandlerError::new(origin, error),
+        };
+
+        let hook = self.inner.error_hook.borrow().clone();
+        match hook {
+            Some(hook) => hook(self, &error),
+            None => error!("Handler failed: {}", error),
+        }
+
+        let reply = self.inner.error_reply.borrow().clone();
+        if let (Some(text), Some(chat_id)) = (reply, error.origin.chat_id) {
+            let mut msg = self.message(chat_id, text);
+            if let Some(id) = error.origin.message_id {
+                msg = msg.reply_to_message_id(id);
+            }
+
+            self.inner.handle.spawn(
+                msg.send()
+                    .map(|_| ())
+                    .map_err(|e| error!("Couldn't send the error reply: {}", e)),
+            );
+        }
+    }
+
+    /// Enables or disables the automatic answer to `/help`, which lists the described commands.
+    /// It is only sent if no handler is registered for `/help`. Commands scoped to chat
+    /// administrators are left out, they are only shown in the command menu of Telegram.
+    pub fn auto_help(self, enabled: bool) -> RcBot {
+        self.inner.auto_help.set(enabled);
+
+        self
+    }
+
+    /// Sets the backend of the session store, the default store keeps all sessions in memory
+    pub fn session_store<S>(self, store: S) -> RcBot
+    where
+        S: SessionStore + 'static,
+    {
+        *self.inner.session_store.borrow_mut() = Rc::new(store);
+
+        self
+    }
+
+    /// Returns the session store to read and write per-chat or per-user data
+    pub fn session(&self) -> Session {
+        Session::new(self.inner.session_store.borrow().clone())
+    }
+
     /// Creates a new command and returns a stream which will yield a message when the command is send
-    pub fn new_cmd(
+    ///
+    /// The text of the yielded message is replaced by the arguments of the command.
+    pub fn new_cmd(&self, cmd: &str) -> HandlerStream<(RcBot, objects::Message)> {
+        self.new_cmd_with(cmd, CommandOptions::new())
+            .map_items(|(bot, mut msg, command)| {
+                msg.text = Some(command.args);
+
+                (bot, msg)
+            })
+    }
+
+    /// Creates a new command with the given options, e.g. to accept the command in edited messages
+    /// or channel posts as well. The stream yields the unmodified message together with the parsed
+    /// command, which contains the command name and the raw arguments.
+    ///
+    /// The handle of the returned stream removes the command again.
+    pub fn new_cmd_with(
         &self,
         cmd: &str,
-    ) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+        options: CommandOptions,
+    ) -> HandlerStream<(RcBot, objects::Message, Command)> {
+        let (sender, receiver) = mpsc::unbounded();
+        let id = self.inner.next_handler_id();
+
+        self.insert_cmd(cmd, id, options, sender);
+
+        HandlerStream::new(
+            receiver.map_err(|_| Error::from(ErrorKind::Channel)),
+            self.handler_handle(id),
+        )
+    }
+
+    /// Declines a command received from `new_cmd_with`, the command is passed to the handler with
+    /// the next lower priority. If no other handler accepts it, messages go to the text handlers
+    /// and then to the handler of `unknown_cmd`, although the command is registered. Edited
+    /// messages and channel posts go to their handlers.
+    ///
+    /// The update of the message was already passed on when the command was dispatched, so a
+    /// message which nobody accepts is dropped and doesn't appear in the stream of `get_stream`.
+    pub fn decline(&self, message: objects::Message, command: Command) {
+        let inner = &self.inner;
+        let kind = command.kind;
+
+        let message = self.route_to_handlers(message, command);
+
+        let message = match kind {
+            UpdateKind::Message => message
+                .and_then(|msg| self.route_text(msg))
+                .and_then(|msg| self.forward(&inner.unknown_handler, msg)),
+            UpdateKind::EditedMessage => {
+                message.and_then(|msg| self.forward(&inner.edited_message_handler, msg))
+            }
+            UpdateKind::ChannelPost => {
+                message.and_then(|msg| self.forward(&inner.channel_post_handler, msg))
+            }
+            UpdateKind::EditedChannelPost => {
+                message.and_then(|msg| self.forward(&inner.edited_channel_post_handler, msg))
+            }
+        };
+
+        if let Some(message) = message {
+            debug!("Command was declined by all handlers: {:?}", message.text);
+        }
+    }
+
+    /// Registers every command of a typed command set and returns a stream which yields the
+    /// parsed commands. Commands with invalid arguments are yielded as a usage error, which
+    /// can be sent back to the user. The handle of the stream removes all commands of the set.
+    pub fn typed_cmds<T>(&self) -> HandlerStream<(RcBot, objects::Message, Result<T, UsageError>)>
+    where
+        T: BotCommands + 'static,
+    {
         let (sender, receiver) = mpsc::unbounded();
+        let id = self.inner.next_handler_id();
+
+        for (name, description) in T::descriptions() {
+            let options = if description.is_empty() {
+                CommandOptions::new()
+            } else {
+                CommandOptions::new().description(description)
+            };
+
+            self.insert_cmd(name, id, options, sender.clone());
+        }
+
+        let stream = receiver
+            .map_err(|_| Error::from(ErrorKind::Channel))
+            .map(|(bot, msg, command)| {
+                let parsed = T::parse(&command);
+
+                (bot, msg, parsed)
+            });
+
+        HandlerStream::new(stream, self.handler_handle(id))
+    }
+
+    /// Registers the `/start` command and returns a stream which yields the decoded payload of a
+    /// deep link. Payloads which weren't created by `deep_link` are yielded as None.
+    pub fn start_cmd(&self) -> HandlerStream<(RcBot, objects::Message, Option<String>)> {
+        self.new_cmd_with("/start", CommandOptions::new())
+            .map_items(|(bot, msg, command)| {
+                let payload = if command.args.is_empty() {
+                    None
+                } else {
+                    deeplink::decode_payload(&command.args)
+                        .map_err(|e| debug!("Invalid start payload {}: {}", command.args, e))
+                        .ok()
+                };
+
+                (bot, msg, payload)
+            })
+    }
+
+    /// Builds a link which opens a private chat with the bot and sends the payload with
+    /// `/start`. The name of the bot has to be resolved, which `run` does on startup.
+    pub fn deep_link(&self, payload: &str) -> Result<String, Error> {
+        match *self.inner.name.borrow() {
+            Some(ref name) => deeplink::start_link(name, payload),
+            None => Err(Error::from(ErrorKind::NoBotName)),
+        }
+    }
+
+    /// Builds a link which adds the bot to a group and sends the payload with `/start`
+    pub fn deep_link_group(&self, payload: &str) -> Result<String, Error> {
+        match *self.inner.name.borrow() {
+            Some(ref name) => deeplink::startgroup_link(name, payload),
+            None => Err(Error::from(ErrorKind::NoBotName)),
+        }
+    }
+
+    /// Removes the handler of a registration, returns false if it was already removed
+    pub fn unregister(&self, handle: &handler::Handle) -> bool {
+        self.inner.remove_handler(handle.id())
+    }
 
+    fn handler_handle(&self, id: usize) -> handler::Handle {
+        handler::Handle::new(Rc::downgrade(&self.inner), id)
+    }
+
+    /// Adds the handler for a command, a missing leading slash is prepended to the name. The
+    /// handlers of a command are sorted by their priority, an existing handler with the same
+    /// priority is replaced.
+    fn insert_cmd(
+        &self,
+        cmd: &str,
+        id: usize,
+        options: CommandOptions,
+        sender: UnboundedSender<(RcBot, objects::Message, Command)>,
+    ) {
         let cmd = if cmd.starts_with("/") {
             cmd.into()
         } else {
             format!("/{}", cmd)
         };
 
-        self.inner.handlers.borrow_mut().insert(cmd.into(), sender);
+        let mut handlers = self.inner.handlers.borrow_mut();
+        let handlers = handlers.entry(cmd.clone()).or_insert_with(Vec::new);
 
-        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+        let priority = options.priority;
+        let handler = CommandHandler {
+            id,
+            options,
+            sender,
+        };
+
+        match handlers
+            .iter()
+            .position(|handler| handler.options.priority == priority)
+        {
+            Some(idx) => {
+                warn!(
+                    "The handler of {} with priority {} was replaced by a new registration",
+                    cmd, priority
+                );
+                handlers[idx] = handler;
+            }
+            None => {
+                handlers.push(handler);
+                handlers.sort_by(|a, b| b.options.priority.cmp(&a.options.priority));
+            }
+        }
     }
 
-    /// Returns a stream which will yield a message when none of previously registered commands matches
-    pub fn unknown_cmd(&self) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+    /// Returns the described commands grouped by their scope and sorted by name. Only the first
+    /// described handler of a command is used.
+    pub fn described_commands(
+        &self,
+    ) -> HashMap<Option<objects::BotCommandScope>, Vec<objects::BotCommand>> {
+        let handlers = self.inner.handlers.borrow();
+        let mut names = handlers.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let mut scopes = HashMap::new();
+        for name in names {
+            if let Some(handler) = handlers[name]
+                .iter()
+                .find(|handler| handler.options.description.is_some())
+            {
+                scopes
+                    .entry(handler.options.scope.clone())
+                    .or_insert_with(Vec::new)
+                    .push(objects::BotCommand {
+                        command: name.trim_left_matches('/').into(),
+                        description: handler.options.description.clone().unwrap_or_default(),
+                    });
+            }
+        }
+
+        scopes
+    }
+
+    /// Sends the described commands to Telegram, which shows them in the command menu of the
+    /// clients. This is called by `run` when the bot starts.
+    pub fn sync_commands(&self) -> impl Future<Item = (), Error = Error> {
+        let requests = self.described_commands()
+            .into_iter()
+            .map(|(scope, commands)| {
+                let mut request = self.set_my_commands(commands);
+                if let Some(scope) = scope {
+                    request = request.scope(scope);
+                }
+
+                request.send().map(|_| ())
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests).map(|_| ())
+    }
+
+    /// Returns the text of the automatic help for the chat of the message, or None if no
+    /// described command is visible there
+    fn help_text(&self, message: &objects::Message) -> Option<String> {
+        let handlers = self.inner.handlers.borrow();
+        let mut names = handlers.keys().collect::<Vec<_>>();
+        names.sort();
+
+        let lines = names
+            .into_iter()
+            .filter_map(|name| {
+                handlers[name]
+                    .iter()
+                    .filter(|handler| handler.options.visible_in(message))
+                    .filter_map(|handler| handler.options.description.as_ref())
+                    .next()
+                    .map(|description| format!("{} - {}", name, description))
+            })
+            .collect::<Vec<_>>();
+
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.join("\n"))
+        }
+    }
+
+    /// Returns a stream which will yield messages whose text or caption matches the regular
+    /// expression. Text handlers are checked in the order of registration after the commands,
+    /// only the first matching handler receives the message.
+    pub fn on_text(
+        &self,
+        regex: Regex,
+    ) -> HandlerStream<(RcBot, objects::Message, Captures)> {
+        self.add_text_handler(regex, None)
+    }
+
+    /// Like `on_text`, but the message is only passed to the handler if the filter accepts it
+    pub fn on_text_with<F>(
+        &self,
+        regex: Regex,
+        filter: F,
+    ) -> HandlerStream<(RcBot, objects::Message, Captures)>
+    where
+        F: Filter + 'static,
+    {
+        self.add_text_handler(regex, Some(Rc::new(filter)))
+    }
+
+    fn add_text_handler(
+        &self,
+        regex: Regex,
+        filter: Option<Rc<Filter>>,
+    ) -> HandlerStream<(RcBot, objects::Message, Captures)> {
         let (sender, receiver) = mpsc::unbounded();
+        let id = self.inner.next_handler_id();
 
-        *self.inner.unknown_handler.borrow_mut() = Some(sender);
+        self.inner.text_handlers.borrow_mut().push(TextHandler {
+            id,
+            regex,
+            filter,
+            sender,
+        });
 
-        receiver.then(|x| x.map_err(|_| Error::from(ErrorKind::Channel)))
+        HandlerStream::new(
+            receiver.map_err(|_| Error::from(ErrorKind::Channel)),
+            self.handler_handle(id),
+        )
     }
 
-    /// Register a new commnd
-    pub fn register<T>(&self, hnd: T)
+    /// Returns a stream which will yield every callback query of an inline keyboard. Callback
+    /// handlers are checked in the order of registration, only the first one receives the query.
+    pub fn callback_queries(
+        &self,
+    ) -> HandlerStream<(RcBot, objects::CallbackQuery)> {
+        self.add_callback_handler(None)
+    }
+
+    /// Like `callback_queries`, but the query is only passed to the handler if the filter accepts
+    /// it
+    pub fn callback_queries_with<F>(
+        &self,
+        filter: F,
+    ) -> HandlerStream<(RcBot, objects::CallbackQuery)>
     where
-        T: Stream + 'static,
+        F: Filter + 'static,
     {
-        self.inner.handle.spawn(
-            hnd.for_each(|_| Ok(()))
-                .into_future()
-                .map(|_| ())
-                .map_err(|_| ()),
-        );
+        self.add_callback_handler(Some(Rc::new(filter)))
     }
 
-    /// The main update loop, the update function is called every update_interval milliseconds
-    /// When an update is available the last_id will be updated and the message is filtered
-    /// for commands
-    /// The message is forwarded to the returned stream if no command was found
-    pub fn get_stream<'a>(
-        &'a self,
-    ) -> impl Stream<Item = (RcBot, objects::Update), Error = Error> + 'a {
-        use functions::*;
+    fn add_callback_handler(
+        &self,
+        filter: Option<Rc<Filter>>,
+    ) -> HandlerStream<(RcBot, objects::CallbackQuery)> {
+        let (sender, receiver) = mpsc::unbounded();
+        let id = self.inner.next_handler_id();
 
-        let duration = Duration::from_millis(self.inner.update_interval.get());
-        Interval::new(duration, &self.inner.handle)
-            .into_future()
+        self.inner.callback_handlers.borrow_mut().push(CallbackHandler {
+            id,
+            filter,
+            sender,
+        });
+
+        HandlerStream::new(
+            receiver.map_err(|_| Error::from(ErrorKind::Channel)),
+            self.handler_handle(id),
+        )
+    }
+
+    /// Returns a stream which will yield the service messages of the given kinds together with the
+    /// event they describe. Event handlers are checked in the order of registration, only the
+    /// first one which accepts the kind receives the message.
+    pub fn chat_events(
+        &self,
+        kinds: &[EventKind],
+    ) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        let (sender, receiver) = mpsc::unbounded();
+        let id = self.inner.next_handler_id();
+
+        self.inner.event_handlers.borrow_mut().push(EventHandler {
+            id,
+            kinds: kinds.to_vec(),
+            sender,
+        });
+
+        HandlerStream::new(
+            receiver.map_err(|_| Error::from(ErrorKind::Channel)),
+            self.handler_handle(id),
+        )
+    }
+
+    /// Returns a stream which will yield every user who joined a chat
+    pub fn member_joins(&self) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        self.chat_events(&[EventKind::MemberJoined])
+    }
+
+    /// Returns a stream which will yield every user who left a chat
+    pub fn member_leaves(&self) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        self.chat_events(&[EventKind::MemberLeft])
+    }
+
+    /// Returns a stream which will yield every change of a chat title
+    pub fn title_changes(&self) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        self.chat_events(&[EventKind::TitleChanged])
+    }
+
+    /// Returns a stream which will yield every change or removal of a chat photo
+    pub fn photo_changes(&self) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        self.chat_events(&[EventKind::PhotoChanged, EventKind::PhotoDeleted])
+    }
+
+    /// Returns a stream which will yield every pinned message
+    pub fn pins(&self) -> HandlerStream<(RcBot, objects::Message, ChatEvent)> {
+        self.chat_events(&[EventKind::MessagePinned])
+    }
+
+    /// Adds a job which runs on the given schedule. Errors of the job are passed to the error
+    /// hook.
+    pub fn schedule<F, R>(&self, schedule: Schedule, job: F) -> Result<JobId, Error>
+    where
+        F: Fn(RcBot) -> R + 'static,
+        R: IntoFuture<Item = (), Error = Error> + 'static,
+    {
+        let id = self.inner
+            .scheduler
+            .add(schedule, None, move |bot, _| job(bot))?;
+        self.start_scheduler();
+
+        Ok(id)
+    }
+
+    /// Adds a job which belongs to a chat and receives its id. The id is updated when the group is
+    /// upgraded to a supergroup.
+    pub fn schedule_for_chat<F, R>(
+        &self,
+        chat_id: objects::Integer,
+        schedule: Schedule,
+        job: F,
+    ) -> Result<JobId, Error>
+    where
+        F: Fn(RcBot, objects::Integer) -> R + 'static,
+        R: IntoFuture<Item = (), Error = Error> + 'static,
+    {
+        let id = self.inner.scheduler.add(schedule, Some(chat_id), move |bot, chat_id| {
+            job(bot, chat_id.unwrap_or(0))
+        })?;
+        self.start_scheduler();
+
+        Ok(id)
+    }
+
+    /// Removes a job, returns false if it doesn't exist or already finished
+    pub fn cancel_job(&self, id: JobId) -> bool {
+        self.inner.scheduler.cancel(id)
+    }
+
+    /// Starts all jobs which are due according to the clock. This happens once a second on its
+    /// own, but can be called to test schedules with a `ManualClock`.
+    pub fn run_due_jobs(&self) {
+        self.inner.scheduler.tick(self);
+    }
+
+    /// Checks for due jobs once a second, the timer is started with the first job
+    fn start_scheduler(&self) {
+        if !self.inner.scheduler.start() {
+            return;
+        }
+
+        let bot = self.clone();
+        let timer = Interval::new(Duration::from_secs(1), &self.inner.handle)
+            .into_future()
+            .into_stream()
+            .flatten()
+            .for_each(move |_| {
+                bot.run_due_jobs();
+
+                Ok(())
+            })
+            .map_err(|e| error!("The timer of the scheduler failed: {}", e));
+
+        self.inner.handle.spawn(timer);
+    }
+
+    /// Sets the durable store of scheduled calls and schedules all calls it contains. Calls which
+    /// are already due are sent right away.
+    ///
+    /// Calls which were scheduled with the previous store are moved to the new one. Calls with a
+    /// due time out of range are skipped and stay in the store.
+    pub fn call_store<S>(self, store: S) -> Result<RcBot, Error>
+    where
+        S: CallStore + 'static,
+    {
+        // calls which were scheduled before are moved to the new store, their jobs stay
+        let previous = self.inner.call_store.borrow().clone();
+        for call in previous.all()? {
+            if store.get(&call.id)?.is_none() {
+                store.insert(call)?;
+            }
+        }
+
+        let calls = store.all()?;
+        *self.inner.call_store.borrow_mut() = Rc::new(store);
+
+        for call in calls {
+            if call.due().is_none() {
+                error!("Skipped the scheduled call {} with the due time {}", call.id, call.due);
+                continue;
+            }
+
+            if !self.inner.call_jobs.borrow().contains_key(&call.id) {
+                self.add_call_job(&call)?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Schedules a call of a Telegram method for the chat, the chat id is added to the
+    /// parameters. Returns the id of the scheduled call.
+    pub fn schedule_call(
+        &self,
+        chat_id: objects::Integer,
+        method: &str,
+        params: Value,
+        due: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let call = ScheduledCall::new(chat_id, method, params, due);
+
+        self.inner.call_store.borrow().insert(call.clone())?;
+        self.add_call_job(&call)?;
+
+        Ok(call.id)
+    }
+
+    /// Schedules a text message for the chat
+    pub fn schedule_message(
+        &self,
+        chat_id: objects::Integer,
+        text: &str,
+        due: DateTime<Utc>,
+    ) -> Result<String, Error> {
+        let mut params = serde_json::Map::new();
+        params.insert("text".into(), Value::from(text));
+
+        self.schedule_call(chat_id, "sendMessage", Value::Object(params), due)
+    }
+
+    /// Returns the scheduled calls of a chat sorted by their due time
+    pub fn scheduled_calls(&self, chat_id: objects::Integer) -> Result<Vec<ScheduledCall>, Error> {
+        let mut calls = self.inner
+            .call_store
+            .borrow()
+            .all()?
+            .into_iter()
+            .filter(|call| call.chat_id == chat_id)
+            .collect::<Vec<_>>();
+
+        calls.sort_by_key(|call| call.due);
+
+        Ok(calls)
+    }
+
+    /// Cancels a scheduled call, returns false if it doesn't exist or was already sent
+    pub fn cancel_scheduled_call(&self, id: &str) -> Result<bool, Error> {
+        if let Some(job) = self.inner.call_jobs.borrow_mut().remove(id) {
+            self.inner.scheduler.cancel(job);
+        }
+
+        Ok(self.inner.call_store.borrow().remove(id)?.is_some())
+    }
+
+    /// Adds the job which sends a stored call at its due time. The call is taken from the store
+    /// when it is sent, so a cancelled or migrated call is never sent to the old chat.
+    fn add_call_job(&self, call: &ScheduledCall) -> Result<(), Error> {
+        let id = call.id.clone();
+
+        let send = move |bot: RcBot| -> Box<Future<Item = (), Error = Error>> {
+            bot.inner.call_jobs.borrow_mut().remove(&id);
+
+            let store = bot.inner.call_store.borrow().clone();
+            let call = match store.get(&id) {
+                Ok(Some(call)) => call,
+                Ok(None) => return Box::new(future::ok(())),
+                Err(e) => return Box::new(future::err(e)),
+            };
+
+            debug!("Send the scheduled call {} to chat {}", call.id, call.chat_id);
+
+            let body = match call.body() {
+                Ok(body) => body,
+                Err(e) => {
+                    let removed = store.remove(&call.id);
+                    return Box::new(future::err(removed.err().unwrap_or(e)));
+                }
+            };
+
+            // the call stays in the store until it was sent, a failed call is sent again later
+            // unless Telegram rejected it
+            let fut = bot.inner
+                .fetch_json(&call.method, &body)
+                .then(move |result| -> Result<(), Error> {
+                    let e = match result {
+                        Ok(_) => return store.remove(&call.id).map(|_| ()),
+                        Err(e) => e,
+                    };
+
+                    match call_retry_delay(&e) {
+                        Some(delay) => {
+                            let mut call = call;
+                            call.due = bot.inner.scheduler.now().timestamp() + delay;
+
+                            warn!("Send the scheduled call {} again in {}s", call.id, delay);
+
+                            store.insert(call.clone())?;
+                            bot.add_call_job(&call)?;
+                        }
+                        None => {
+                            store.remove(&call.id)?;
+                        }
+                    }
+
+                    Err(e)
+                });
+
+            Box::new(fut)
+        };
+
+        let due = call.due().ok_or(ErrorKind::InvalidDueTime)?;
+        let job = self.schedule(Schedule::At(due), send)?;
+
+        self.inner.call_jobs.borrow_mut().insert(call.id.clone(), job);
+
+        Ok(())
+    }
+
+    /// Moves the stored calls of a group to the supergroup it was upgraded to
+    fn migrate_calls(&self, from: objects::Integer, to: objects::Integer) -> Result<(), Error> {
+        let store = self.inner.call_store.borrow().clone();
+
+        for mut call in store.all()?.into_iter().filter(|call| call.chat_id == from) {
+            call.chat_id = to;
+            store.insert(call)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the data of a group to the id of the supergroup it was upgraded to. This is called
+    /// by the dispatcher when the migration is reported.
+    ///
+    /// The jobs, the scheduled calls, the sessions and the state of interceptors like dialogues
+    /// and waiting messages are moved. Other data keyed by chat ids, e.g. the admin cache of an
+    /// `AdminGuard`, keeps the old id and has to be moved by a handler of `ChatEvent::Migrated`.
+    pub fn migrate_chat(&self, from: objects::Integer, to: objects::Integer) {
+        debug!("Chat {} was migrated to {}", from, to);
+
+        let interceptors = self.inner.interceptors.borrow().clone();
+        for interceptor in interceptors {
+            interceptor.migrate_chat(from, to);
+        }
+
+        self.inner.scheduler.migrate_chat(from, to);
+        self.migrate_calls(from, to)
+            .unwrap_or_else(|e| error!("Couldn't move the calls of chat {}: {}", from, e));
+
+        self.inner
+            .session_store
+            .borrow()
+            .rename(SessionKey::Chat(from), SessionKey::Chat(to))
+            .unwrap_or_else(|e| error!("Couldn't move the sessions of chat {}: {}", from, e));
+    }
+
+    /// Returns a stream which will yield a message when none of previously registered commands matches.
+    /// Commands which were declined by all of their handlers are yielded as well.
+    pub fn unknown_cmd(&self) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.unknown_handler.borrow_mut() = Some(sender);
+
+        receiver.then(|x| x.map_err(|_| Error::from(ErrorKind::Channel)))
+    }
+
+    /// Removes the handler of `unknown_cmd`, unknown commands are forwarded to the stream of
+    /// `get_stream` again
+    pub fn remove_unknown_cmd(&self) {
+        *self.inner.unknown_handler.borrow_mut() = None;
+    }
+
+    /// Returns a stream which will yield every edited message which isn't handled by a command
+    pub fn edited_messages(&self) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.edited_message_handler.borrow_mut() = Some(sender);
+
+        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+    }
+
+    /// Returns a stream which will yield every channel post which isn't handled by a command
+    pub fn channel_posts(&self) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.channel_post_handler.borrow_mut() = Some(sender);
+
+        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+    }
+
+    /// Returns a stream which will yield every edited channel post which isn't handled by a
+    /// command
+    pub fn edited_channel_posts(
+        &self,
+    ) -> impl Stream<Item = (RcBot, objects::Message), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.edited_channel_post_handler.borrow_mut() = Some(sender);
+
+        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+    }
+
+    /// Returns a stream which will yield every inline query sent to the bot. Inline queries are no
+    /// longer forwarded to the stream of `get_stream` once this is called.
+    pub fn inline_queries(
+        &self,
+    ) -> impl Stream<Item = (RcBot, objects::InlineQuery), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.inline_query_handler.borrow_mut() = Some(sender);
+
+        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+    }
+
+    /// Returns a stream which will yield every inline result chosen by a user. Telegram only
+    /// sends these updates if inline feedback is enabled with BotFather.
+    pub fn chosen_inline_results(
+        &self,
+    ) -> impl Stream<Item = (RcBot, objects::ChosenInlineResult), Error = Error> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        *self.inner.chosen_inline_result_handler.borrow_mut() = Some(sender);
+
+        receiver.map_err(|_| Error::from(ErrorKind::Channel))
+    }
+
+    /// Adds a middleware which runs for every update before it is dispatched. Middleware runs in
+    /// the order in which it was added.
+    pub fn add_middleware<M>(&self, middleware: M)
+    where
+        M: Middleware + 'static,
+    {
+        self.inner.middlewares.borrow_mut().push(Rc::new(middleware));
+    }
+
+    /// Adds a dialogue, messages of users in a conversation are passed to the dialogue before
+    /// they are checked for commands. Conversations which timed out are reset periodically.
+    pub fn add_dialogue<S>(&self, dialogue: &Dialogue<S>)
+    where
+        S: Clone + Eq + Hash + 'static,
+    {
+        self.add_interceptor(dialogue.clone());
+
+        let sweep = dialogue.clone();
+        let timer = Interval::new(Duration::from_secs(EXPIRE_INTERVAL), &self.inner.handle)
+            .into_future()
+            .into_stream()
+            .flatten()
+            .for_each(move |_| {
+                sweep.expire();
+
+                Ok(())
+            })
+            .map_err(|e| error!("The timer of the dialogue failed: {}", e));
+
+        self.inner.handle.spawn(timer);
+    }
+
+    /// Adds an interceptor which sees new messages before they are checked for commands.
+    /// Interceptors are checked in the order in which they were added.
+    pub fn add_interceptor<I>(&self, interceptor: I)
+    where
+        I: Interceptor + 'static,
+    {
+        self.inner.interceptors.borrow_mut().push(Rc::new(interceptor));
+    }
+
+    /// Resolves to the next message of the user in the chat, or to None if the user didn't answer
+    /// in time. The message doesn't reach other handlers. A second wait for the same user and chat
+    /// replaces the first one, which then resolves to None.
+    ///
+    /// The handler which waits is blocked until the future resolves. Register it with
+    /// `register_per_chat` instead of chaining it onto a handler stream, otherwise the handler
+    /// doesn't see the items of other chats in the meantime.
+    pub fn wait_for_message(
+        &self,
+        chat_id: objects::Integer,
+        user_id: objects::Integer,
+        timeout: Duration,
+    ) -> impl Future<Item = Option<objects::Message>, Error = Error> {
+        let waiters = self.inner.waiters.clone();
+        let (id, receiver) = waiters.add(chat_id, user_id);
+
+        let timeout = future::result(Timeout::new(timeout, &self.inner.handle))
+            .and_then(|timeout| timeout)
+            .map(|_| None)
+            .map_err(|e| Error::from(e.context(ErrorKind::Timer)));
+
+        receiver
+            .then(|msg| Ok(msg.ok()))
+            .select(timeout)
+            .map(|(msg, _)| msg)
+            .map_err(|(e, _)| e)
+            .then(move |result| {
+                if waiters.remove(chat_id, user_id, id) {
+                    debug!("User {} didn't answer in chat {}", user_id, chat_id);
+                }
+
+                result
+            })
+    }
+
+    /// Register a new commnd
+    ///
+    /// A failed item doesn't end the stream. The error of the stream can be of any type, so the
+    /// error hook only learns that the handler failed. Use `register_reporting` to pass the
+    /// errors themselves to the hook, or `register_with` to report them together with the chat
+    /// and message of their item.
+    pub fn register<T>(&self, hnd: T)
+    where
+        T: Stream + 'static,
+    {
+        self.register_reporting(hnd.map_err(|_| Error::from(ErrorKind::Handler)));
+    }
+
+    /// Registers a handler stream like `register` and passes its errors to the error hook. The
+    /// item is unknown here, so the error has no origin unless the handler returns a
+    /// `HandlerError`. Without an origin the hook gets no chat or message and the `error_reply`
+    /// can't be sent.
+    pub fn register_reporting<T>(&self, hnd: T)
+    where
+        T: Stream + 'static,
+        T::Error: Into<Error>,
+    {
+        let bot = self.clone();
+
+        self.inner.handle.spawn(
+            hnd.then(move |result| -> Result<(), ()> {
+                if let Err(e) = result {
+                    bot.report_error(e.into(), ErrorOrigin::default());
+                }
+
+                Ok(())
+            }).for_each(|_| Ok(())),
+        );
+    }
+
+    /// Registers a handler stream whose items are processed one after another by the given
+    /// function. Errors are passed to the error hook together with the origin of the item.
+    pub fn register_with<S, F, R>(&self, hnd: S, handler: F)
+    where
+        S: Stream<Error = Error> + 'static,
+        S::Item: ChatItem,
+        F: Fn(S::Item) -> R + 'static,
+        R: IntoFuture<Error = Error> + 'static,
+    {
+        let bot = self.clone();
+
+        self.register_reporting(hnd.and_then(move |item| {
+            let bot = bot.clone();
+            let origin = item.origin();
+
+            handler(item).into_future().then(move |result| -> Result<(), Error> {
+                if let Err(e) = result {
+                    bot.report_error(e, origin);
+                }
+
+                Ok(())
+            })
+        }));
+    }
+
+    /// Registers a handler stream whose items are processed by the given function. The futures
+    /// of all handlers registered this way run one after another for the same chat, while
+    /// different chats are handled concurrently up to the limit of `max_parallel`.
+    ///
+    /// The items of this handler start in the order of their arrival. Items of different handlers
+    /// are queued when each handler stream yields them, which can differ from the order of the
+    /// updates, e.g. behind a guard.
+    pub fn register_per_chat<S, F, R>(&self, hnd: S, handler: F)
+    where
+        S: Stream<Error = Error> + 'static,
+        S::Item: ChatItem,
+        F: Fn(S::Item) -> R + 'static,
+        R: IntoFuture<Error = Error> + 'static,
+    {
+        let queue = self.inner.chat_queue.clone();
+        let handler = Rc::new(handler);
+        let bot = self.clone();
+
+        self.register_reporting(hnd.map(move |item| {
+            let chat_id = item.chat_id();
+            let origin = item.origin();
+            let handler = handler.clone();
+            let bot = bot.clone();
+
+            // the handler is only called once the job is started by the queue
+            queue.push(
+                chat_id,
+                future::lazy(move || handler(item).into_future())
+                    .map(|_| ())
+                    .map_err(move |e| bot.report_error(e, origin)),
+            );
+        }));
+    }
+
+    /// The main update loop, the update function is called every update_interval milliseconds
+    /// When an update is available the last_id will be updated and the message is filtered
+    /// for commands
+    /// The message is forwarded to the returned stream if no command was found
+    pub fn get_stream<'a>(
+        &'a self,
+    ) -> impl Stream<Item = (RcBot, objects::Update), Error = Error> + 'a {
+        use functions::*;
+
+        let duration = Duration::from_millis(self.inner.update_interval.get());
+        Interval::new(duration, &self.inner.handle)
+            .into_future()
             .into_stream()
             .flatten()
             .map_err(|x| Error::from(x.context(ErrorKind::IntervalTimer)))
@@ -295,48 +1537,377 @@ impl RcBot {
 
                 Ok(x)
             })
-            .filter_map(move |mut val| {
+            .and_then(move |val| {
                 debug!("Got an update from Telegram: {:?}", val);
 
-                let mut sndr: Option<UnboundedSender<(RcBot, objects::Message)>> = None;
-
-                if let Some(ref mut message) = val.message {
-                    if let Some(text) = message.text.clone() {
-                        let mut content = text.split_whitespace();
-                        if let Some(mut cmd) = content.next() {
-                            if cmd.starts_with("/") {
-                                if let Some(name) = self.inner.name.borrow().as_ref() {
-                                    if cmd.ends_with(name.as_str()) {
-                                        cmd = cmd.rsplitn(2, '@').skip(1).next().unwrap();
-                                    }
-                                }
-                                if let Some(sender) = self.inner.handlers.borrow_mut().get_mut(cmd)
-                                {
-                                    sndr = Some(sender.clone());
-                                    message.text = Some(content.collect::<Vec<&str>>().join(" "));
-                                } else if let Some(ref mut sender) =
-                                    *self.inner.unknown_handler.borrow_mut()
-                                {
-                                    sndr = Some(sender.clone());
-                                }
-                            }
-                        }
-                    }
+                self.process(val)
+            })
+            .filter_map(|val| val)
+    }
+
+    /// Runs the middleware around the dispatch of an update. A failing middleware drops the
+    /// update. Resolves to the update if some part of it wasn't consumed by a handler.
+    ///
+    /// Updates received by `get_stream` are processed automatically, this is meant for updates
+    /// which are dispatched again later, e.g. after a throttle delayed them.
+    pub fn process(
+        &self,
+        val: objects::Update,
+    ) -> Box<Future<Item = Option<(RcBot, objects::Update)>, Error = Error>> {
+        let middlewares = self.inner.middlewares.borrow().clone();
+
+        if middlewares.is_empty() {
+            return Box::new(future::ok(self.dispatch(val)));
+        }
+
+        let update_id = val.update_id;
+        let (bot, bot2, bot3) = (self.clone(), self.clone(), self.clone());
+
+        // run the before hooks until one of them stops the update
+        let before = stream::iter_ok::<_, Error>(middlewares.clone()).fold(
+            (Some(val), 0),
+            move |(val, ran), middleware| -> Box<Future<Item = _, Error = Error>> {
+                match val {
+                    Some(val) => Box::new(middleware.before(&bot, val).map(move |val| (val, ran + 1))),
+                    None => Box::new(future::ok((None, ran))),
                 }
+            },
+        );
 
-                if let Some(sender) = sndr {
-                    sender
-                        .unbounded_send((self.clone(), val.message.unwrap()))
-                        .unwrap_or_else(|e| error!("Error: {}", e));
-                    return None;
-                } else {
-                    return Some((self.clone(), val));
+        let fut = before
+            .map(move |(val, ran)| (val.and_then(|val| bot2.dispatch(val)), ran))
+            .and_then(move |(result, ran)| {
+                // the after hooks run in reverse order for every middleware which saw the update
+                let ran = middlewares.into_iter().take(ran).rev().collect::<Vec<_>>();
+
+                stream::iter_ok::<_, Error>(ran)
+                    .for_each(move |middleware| middleware.after(&bot3, update_id))
+                    .map(move |_| result)
+            })
+            .or_else(|err| {
+                error!("Middleware failed: {}", err);
+
+                Ok(None)
+            });
+
+        Box::new(fut)
+    }
+
+    /// Forwards the parts of an update to the registered handlers. The update is returned if
+    /// some part of it wasn't consumed by a handler.
+    fn dispatch(&self, mut val: objects::Update) -> Option<(RcBot, objects::Update)> {
+        let inner = &self.inner;
+
+        move_extensions(&mut val);
+
+        val.message = val.message
+            .take()
+            .and_then(|msg| self.route_event(msg))
+            .and_then(|msg| self.intercept(msg))
+            .and_then(|msg| self.route_command(UpdateKind::Message, msg))
+            .and_then(|msg| self.route_help(msg))
+            .and_then(|msg| self.route_text(msg))
+            .and_then(|msg| self.route_unknown(msg));
+        val.edited_message = val.edited_message
+            .take()
+            .and_then(|msg| self.route_command(UpdateKind::EditedMessage, msg))
+            .and_then(|msg| self.forward(&inner.edited_message_handler, msg));
+        val.channel_post = val.channel_post
+            .take()
+            .and_then(|msg| self.route_command(UpdateKind::ChannelPost, msg))
+            .and_then(|msg| self.forward(&inner.channel_post_handler, msg));
+        val.edited_channel_post = val.edited_channel_post
+            .take()
+            .and_then(|msg| self.route_command(UpdateKind::EditedChannelPost, msg))
+            .and_then(|msg| self.forward(&inner.edited_channel_post_handler, msg));
+        val.callback_query = val.callback_query
+            .take()
+            .and_then(|query| self.route_callback(query));
+        val.inline_query = val.inline_query
+            .take()
+            .and_then(|query| self.forward(&inner.inline_query_handler, query));
+        val.chosen_inline_result = val.chosen_inline_result
+            .take()
+            .and_then(|result| self.forward(&inner.chosen_inline_result_handler, result));
+
+        let remaining = val.message.is_some() || val.edited_message.is_some()
+            || val.channel_post.is_some() || val.edited_channel_post.is_some()
+            || val.inline_query.is_some() || val.chosen_inline_result.is_some()
+            || val.callback_query.is_some();
+
+        if remaining {
+            Some((self.clone(), val))
+        } else {
+            None
+        }
+    }
+
+    /// Sends a service message to the first event handler which accepts its kind. Migrations are
+    /// applied before, even if no handler is registered.
+    fn route_event(&self, message: objects::Message) -> Option<objects::Message> {
+        let mut events = ChatEvent::from_message(&message);
+        let last = match events.pop() {
+            Some(event) => event,
+            None => return Some(message),
+        };
+
+        // several users joined at once, every one of them gets an event with a copy of the
+        // message, the extensions stay with the last one
+        for event in events {
+            if let Some(message) = self.route_single_event(message.clone(), event) {
+                debug!("No handler for the event of message {}", message.message_id);
+            }
+        }
+
+        self.route_single_event(message, last)
+    }
+
+    fn route_single_event(
+        &self,
+        message: objects::Message,
+        event: ChatEvent,
+    ) -> Option<objects::Message> {
+        if let ChatEvent::Migrated { from, to } = event {
+            self.migrate_chat(from, to);
+        }
+
+        let kind = event.kind();
+        let handler = self.inner
+            .event_handlers
+            .borrow()
+            .iter()
+            .find(|handler| handler.kinds.contains(&kind))
+            .map(|handler| (handler.id, handler.sender.clone()));
+
+        match handler {
+            Some((id, sender)) => match sender.unbounded_send((self.clone(), message, event)) {
+                Ok(()) => None,
+                Err(e) => {
+                    debug!("Removing the dropped handler {}", id);
+                    self.inner.remove_handler(id);
+
+                    let (_, message, event) = e.into_inner();
+                    self.route_single_event(message, event)
                 }
+            },
+            None => Some(message),
+        }
+    }
+
+    /// Passes a new message to the interceptors until one of them takes it
+    fn intercept(&self, message: objects::Message) -> Option<objects::Message> {
+        let interceptors = self.inner.interceptors.borrow().clone();
+
+        interceptors
+            .iter()
+            .fold(Some(message), |msg, interceptor| {
+                msg.and_then(|msg| interceptor.intercept(self, msg))
             })
     }
 
+    /// Checks a message for a registered command and sends it to the handler. Commands addressed
+    /// to other bots are ignored. Returns the message if nobody took it.
+    fn route_command(&self, kind: UpdateKind, message: objects::Message) -> Option<objects::Message> {
+        let mut command = match Command::from_message(&message) {
+            Some(command) => command,
+            None => return Some(message),
+        };
+
+        if !command.is_addressed_to(self.inner.name.borrow().as_ref().map(String::as_str)) {
+            return Some(message);
+        }
+
+        command.kind = kind;
+
+        self.route_to_handlers(message, command)
+    }
+
+    /// Sends the command to the handler with the highest priority which accepts it. Only
+    /// handlers with a priority below the one in the command are tried.
+    fn route_to_handlers(
+        &self,
+        message: objects::Message,
+        mut command: Command,
+    ) -> Option<objects::Message> {
+        let handler = match self.inner.handlers.borrow().get(&command.name) {
+            Some(handlers) => handlers
+                .iter()
+                .filter(|handler| command.priority.map_or(true, |p| handler.options.priority < p))
+                .find(|handler| {
+                    handler.options.accepts(command.kind) && handler.options.passes(self, &message)
+                })
+                .map(|handler| (handler.id, handler.sender.clone(), handler.options.priority)),
+            None => None,
+        };
+
+        let (id, sender, priority) = match handler {
+            Some(handler) => handler,
+            None => return Some(message),
+        };
+
+        let previous = command.priority;
+        command.priority = Some(priority);
+
+        match sender.unbounded_send((self.clone(), message, command)) {
+            Ok(()) => None,
+            Err(e) => {
+                // the stream of the handler was dropped, try the next one
+                debug!("Removing the dropped handler {}", id);
+                self.inner.remove_handler(id);
+
+                let (_, message, mut command) = e.into_inner();
+                command.priority = previous;
+
+                self.route_to_handlers(message, command)
+            }
+        }
+    }
+
+    /// Answers `/help` with the described commands if no handler is registered for it
+    fn route_help(&self, message: objects::Message) -> Option<objects::Message> {
+        if !self.inner.auto_help.get() {
+            return Some(message);
+        }
+
+        let is_help = match Command::from_message(&message) {
+            Some(command) => {
+                command.name == "/help"
+                    && command.is_addressed_to(self.inner.name.borrow().as_ref().map(String::as_str))
+                    && !self.inner.handlers.borrow().contains_key("/help")
+            }
+            None => false,
+        };
+
+        let text = if is_help {
+            self.help_text(&message)
+        } else {
+            None
+        };
+
+        match text {
+            Some(text) => {
+                self.inner.handle.spawn(
+                    self.message(message.chat.id, text)
+                        .send()
+                        .map(|_| ())
+                        .map_err(|e| error!("Couldn't send the help: {}", e)),
+                );
+                None
+            }
+            None => Some(message),
+        }
+    }
+
+    /// Sends the message to the first text handler which matches its text or caption
+    fn route_text(&self, message: objects::Message) -> Option<objects::Message> {
+        let matched = match message.text.as_ref().or(message.caption.as_ref()) {
+            Some(text) => self.inner
+                .text_handlers
+                .borrow()
+                .iter()
+                .filter(|handler| filter::passes(&handler.filter, self, &message))
+                .filter_map(|handler| {
+                    handler.regex.captures(text).map(|caps| {
+                        (
+                            handler.id,
+                            handler.sender.clone(),
+                            Captures::new(&handler.regex, &caps),
+                        )
+                    })
+                })
+                .next(),
+            None => None,
+        };
+
+        match matched {
+            Some((id, sender, caps)) => match sender.unbounded_send((self.clone(), message, caps)) {
+                Ok(()) => None,
+                Err(e) => {
+                    debug!("Removing the dropped handler {}", id);
+                    self.inner.remove_handler(id);
+
+                    self.route_text(e.into_inner().1)
+                }
+            },
+            None => Some(message),
+        }
+    }
+
+    /// Sends the callback query to the first callback handler whose filter accepts it
+    fn route_callback(&self, query: objects::CallbackQuery) -> Option<objects::CallbackQuery> {
+        let sender = self.inner
+            .callback_handlers
+            .borrow()
+            .iter()
+            .find(|handler| filter::passes(&handler.filter, self, &query))
+            .map(|handler| (handler.id, handler.sender.clone()));
+
+        match sender {
+            Some((id, sender)) => match sender.unbounded_send((self.clone(), query)) {
+                Ok(()) => None,
+                Err(e) => {
+                    debug!("Removing the dropped handler {}", id);
+                    self.inner.remove_handler(id);
+
+                    self.route_callback(e.into_inner().1)
+                }
+            },
+            None => Some(query),
+        }
+    }
+
+    /// Sends commands which weren't handled by anybody to the unknown handler. Commands with a
+    /// registered handler, which didn't accept the message, are not unknown and passed on.
+    fn route_unknown(&self, message: objects::Message) -> Option<objects::Message> {
+        let is_command = match Command::from_message(&message) {
+            Some(command) => {
+                command.is_addressed_to(self.inner.name.borrow().as_ref().map(String::as_str))
+                    && !self.inner.handlers.borrow().contains_key(&command.name)
+            }
+            None => false,
+        };
+
+        if is_command {
+            self.forward(&self.inner.unknown_handler, message)
+        } else {
+            Some(message)
+        }
+    }
+
+    /// Sends a value to the handler if one is registered, otherwise the value is returned. A
+    /// handler whose stream was dropped is removed.
+    fn forward<T>(
+        &self,
+        handler: &RefCell<Option<UnboundedSender<(RcBot, T)>>>,
+        value: T,
+    ) -> Option<T> {
+        let sender = match *handler.borrow() {
+            Some(ref sender) => sender.clone(),
+            None => return Some(value),
+        };
+
+        match sender.unbounded_send((self.clone(), value)) {
+            Ok(()) => None,
+            Err(e) => {
+                debug!("Removing a dropped handler");
+                *handler.borrow_mut() = None;
+
+                Some(e.into_inner().1)
+            }
+        }
+    }
+
     /// helper function to start the event loop
     pub fn run<'a>(&'a self, core: &mut Core) -> Result<(), Error> {
+        self.start();
+
+        core.run(self.get_stream().for_each(|_| Ok(())).into_future())
+            .context(ErrorKind::Tokio)
+            .map_err(Error::from)
+    }
+
+    /// Spawns the tasks which run when the bot starts, this is done by `run`
+    pub fn start(&self) {
         // create a local copy of the bot to circumvent lifetime issues
         let bot = self.inner.clone();
         // create a new task which resolves the bot name and then set it in the struct
@@ -348,8 +1919,62 @@ impl RcBot {
             });
         // spawn the task
         self.inner.handle.spawn(resolve_name.map_err(|_| ()));
-        core.run(self.get_stream().for_each(|_| Ok(())).into_future())
-            .context(ErrorKind::Tokio)
-            .map_err(Error::from)
+        // push the described commands to the command menu
+        if !self.described_commands().is_empty() {
+            self.inner.handle.spawn(
+                self.sync_commands()
+                    .map_err(|e| error!("Couldn't set the commands: {}", e)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::Async;
+
+    fn command_update(text: &str) -> objects::Update {
+        let update = format!(
+            r#"{{
+                "update_id": 1,
+                "message": {{
+                    "message_id": 1,
+                    "date": 0,
+                    "chat": {{ "id": 2, "type": "private" }},
+                    "text": "{}",
+                    "entities": [{{ "type": "bot_command", "offset": 0, "length": {} }}]
+                }}
+            }}"#,
+            text,
+            text.len()
+        );
+
+        serde_json::from_str(&update).unwrap()
+    }
+
+    #[test]
+    fn declined_commands_reach_the_unknown_handler() {
+        let mut core = Core::new().unwrap();
+        let bot = RcBot::new(core.handle(), "");
+
+        let mut votes = bot.new_cmd_with("/vote", CommandOptions::new());
+        let mut unknown = bot.unknown_cmd();
+
+        let rest = core.run(bot.process(command_update("/vote"))).unwrap();
+        assert!(rest.is_none());
+
+        let (bot, msg, command) = match core.run(future::lazy(|| votes.poll())).unwrap() {
+            Async::Ready(Some(item)) => item,
+            _ => panic!("The command wasn't sent to its handler"),
+        };
+
+        bot.decline(msg, command);
+
+        match core.run(future::lazy(|| unknown.poll())).unwrap() {
+            Async::Ready(Some((_, msg))) => assert_eq!(msg.text, Some("/vote".into())),
+            _ => panic!("The declined command wasn't sent to the unknown handler"),
+        }
     }
 }
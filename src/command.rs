@@ -0,0 +1,593 @@
+//! Command parsing and the options of command handlers
+//!
+//! A command handler is created with `RcBot::new_cmd` or `RcBot::new_cmd_with`. The options
+//! define in which kind of updates the command is recognized. Commands are detected with the
+//! `bot_command` entities Telegram attaches to the text or caption of a message.
+//!
+//! A set of typed commands can be derived for an enum with `#[derive(BotCommands)]` from the
+//! telebot-derive crate. Each variant is a command and its fields are the arguments:
+//!
+//! ```rust,ignore
+//! #[derive(BotCommands)]
+//! enum Cmd {
+//!     /// Sends a location to the chat
+//!     Location { latitude: f32, longitude: f32 },
+//!     /// Greets a user, optionally with a custom text
+//!     #[command = "hello"]
+//!     Greet { name: String, #[rest] text: Option<String> },
+//! }
+//!
+//! let handle = bot.typed_cmds::<Cmd>()
+//!     .and_then(|(bot, msg, cmd)| match cmd {
+//!         Ok(Cmd::Location { latitude, longitude }) => ...,
+//!         Ok(Cmd::Greet { name, text }) => ...,
+//!         Err(usage) => bot.message(msg.chat.id, usage.to_string()).send(),
+//!     });
+//! ```
+//!
+//! Fields are parsed with `FromStr`, `Option` fields are optional trailing arguments and a field
+//! marked with `#[rest]` takes the remaining text of the line. Arguments containing whitespace
+//! can be quoted with double quotes.
+
+use std::{rc::Rc, str::FromStr};
+
+use bot::RcBot;
+use objects::{BotCommandScope, Message, MessageEntity};
+use error::UsageError;
+use filter::{self, Filter, Filterable};
+
+/// The kind of update a message was delivered with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UpdateKind {
+    /// A new incoming message of any kind
+    Message,
+    /// A new version of a message that is known to the bot and was edited
+    EditedMessage,
+    /// A new incoming channel post of any kind
+    ChannelPost,
+    /// A new version of a channel post that is known to the bot and was edited
+    EditedChannelPost,
+}
+
+/// Options of a command handler, e.g. which kinds of updates are checked for the command
+#[derive(Clone)]
+pub struct CommandOptions {
+    pub kinds: Vec<UpdateKind>,
+    pub filter: Option<Rc<Filter>>,
+    pub priority: i32,
+    pub description: Option<String>,
+    pub scope: Option<BotCommandScope>,
+}
+
+impl CommandOptions {
+    /// Creates the default options, which only accept commands sent in new messages
+    pub fn new() -> CommandOptions {
+        CommandOptions {
+            kinds: vec![UpdateKind::Message],
+            filter: None,
+            priority: 0,
+            description: None,
+            scope: None,
+        }
+    }
+
+    /// Replaces the kinds of updates in which the command is accepted
+    pub fn kinds(mut self, kinds: &[UpdateKind]) -> CommandOptions {
+        self.kinds = kinds.to_vec();
+
+        self
+    }
+
+    /// Accepts the command additionally in the given kind of update
+    pub fn accept(mut self, kind: UpdateKind) -> CommandOptions {
+        if !self.kinds.contains(&kind) {
+            self.kinds.push(kind);
+        }
+
+        self
+    }
+
+    /// Only passes the command to the handler if the filter accepts the message. Filters added
+    /// before are combined with the new one.
+    pub fn filter<F: Filter + 'static>(mut self, filter: F) -> CommandOptions {
+        self.filter = match self.filter.take() {
+            Some(prev) => Some(Rc::new(move |bot: &RcBot, item: &Filterable| {
+                prev.check(bot, item) && filter.check(bot, item)
+            })),
+            None => Some(Rc::new(filter)),
+        };
+
+        self
+    }
+
+    /// Sets the priority of the handler. Handlers of the same command are tried from the highest
+    /// to the lowest priority, a handler with the same priority as an existing one replaces it.
+    pub fn priority(mut self, priority: i32) -> CommandOptions {
+        self.priority = priority;
+
+        self
+    }
+
+    /// Sets the description of the command. Described commands are listed by the automatic
+    /// `/help` and sent to Telegram for the command menu when the bot starts.
+    pub fn description(mut self, description: &str) -> CommandOptions {
+        self.description = Some(description.into());
+
+        self
+    }
+
+    /// Sets the scope in which the command is listed, by default it is shown in all chats. The
+    /// automatic `/help` never lists commands scoped to chat administrators, since it doesn't
+    /// know whether the sender is one.
+    pub fn scope(mut self, scope: BotCommandScope) -> CommandOptions {
+        self.scope = Some(scope);
+
+        self
+    }
+
+    /// Checks whether the command is listed for the given message. Commands scoped to chat
+    /// administrators are never visible, the admin status of the sender isn't known here and
+    /// the command would be shown to every member otherwise.
+    pub fn visible_in(&self, msg: &Message) -> bool {
+        let user_id = msg.from.as_ref().map(|user| user.id);
+        let is_group = msg.chat.kind == "group" || msg.chat.kind == "supergroup";
+
+        match self.scope {
+            None | Some(BotCommandScope::Default) => true,
+            Some(BotCommandScope::AllPrivateChats) => msg.chat.kind == "private",
+            Some(BotCommandScope::AllGroupChats) => is_group,
+            Some(BotCommandScope::AllChatAdministrators) => false,
+            Some(BotCommandScope::Chat { chat_id }) => msg.chat.id == chat_id,
+            Some(BotCommandScope::ChatAdministrators { .. }) => false,
+            Some(BotCommandScope::ChatMember { chat_id, user_id: member }) => {
+                msg.chat.id == chat_id && user_id == Some(member)
+            }
+        }
+    }
+
+    /// Checks whether the command is accepted in the given kind of update
+    pub fn accepts(&self, kind: UpdateKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    /// Checks whether the filter accepts the message
+    pub fn passes(&self, bot: &RcBot, msg: &Message) -> bool {
+        filter::passes(&self.filter, bot, msg)
+    }
+}
+
+impl Default for CommandOptions {
+    fn default() -> CommandOptions {
+        CommandOptions::new()
+    }
+}
+
+/// A command found at the beginning of a message text or caption
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    /// The command itself including the leading slash, e.g. "/start"
+    pub name: String,
+    /// The bot the command is addressed to without the "@", e.g. "mybot" for "/start@mybot"
+    pub bot_name: Option<String>,
+    /// The raw text following the command, trimmed from surrounding whitespace
+    pub args: String,
+    /// The kind of update the command was received with
+    pub kind: UpdateKind,
+    /// The priority of the handler which received the command, used to pass a declined command
+    /// to the next handler
+    pub priority: Option<i32>,
+}
+
+impl Command {
+    /// Parses a command from a text and its entities. A command is only recognized if a
+    /// `bot_command` entity starts at the beginning of the text.
+    pub fn parse(text: &str, entities: &[MessageEntity]) -> Option<Command> {
+        let entity = entities
+            .iter()
+            .find(|entity| entity.kind == "bot_command" && entity.offset == 0)?;
+
+        // entity lengths are counted in UTF-16 code units
+        let end = utf16_to_byte_offset(text, entity.length as usize)?;
+
+        let mut parts = text[..end].splitn(2, '@');
+        let name = parts.next()?.to_string();
+        let bot_name = parts.next().map(String::from);
+
+        Some(Command {
+            name: name,
+            bot_name: bot_name,
+            args: text[end..].trim().to_string(),
+            kind: UpdateKind::Message,
+            priority: None,
+        })
+    }
+
+    /// Parses the command of a message, either from its text or from the caption of a media
+    /// message
+    pub fn from_message(message: &Message) -> Option<Command> {
+        if let (Some(text), Some(entities)) = (message.text.as_ref(), message.entities.as_ref()) {
+            return Command::parse(text, entities);
+        }
+
+        if let (Some(caption), Some(entities)) =
+            (message.caption.as_ref(), message.caption_entities.as_ref())
+        {
+            return Command::parse(caption, entities);
+        }
+
+        None
+    }
+
+    /// Checks whether the command is meant for a bot with the given username. Commands without
+    /// a bot name are meant for every bot in the chat. If the own name is not known yet, every
+    /// command is accepted.
+    pub fn is_addressed_to(&self, name: Option<&str>) -> bool {
+        match (self.bot_name.as_ref(), name) {
+            (Some(bot_name), Some(name)) => {
+                bot_name.to_lowercase() == name.trim_left_matches('@').to_lowercase()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Converts an offset in UTF-16 code units to a byte offset in the string. Returns None if the
+/// offset doesn't lie on a character boundary or exceeds the string.
+fn utf16_to_byte_offset(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+
+    for (idx, c) in text.char_indices() {
+        if units == offset {
+            return Some(idx);
+        } else if units > offset {
+            return None;
+        }
+
+        units += c.len_utf16();
+    }
+
+    if units == offset {
+        Some(text.len())
+    } else {
+        None
+    }
+}
+
+/// A set of typed commands, usually implemented with `#[derive(BotCommands)]`
+pub trait BotCommands: Sized {
+    /// Parses a command of this set together with its arguments
+    fn parse(command: &Command) -> Result<Self, UsageError>;
+
+    /// Returns the names of all commands in this set, including the leading slash
+    fn names() -> Vec<&'static str>;
+
+    /// Returns the name and the description of each command in this set
+    fn descriptions() -> Vec<(&'static str, &'static str)>;
+
+    /// Returns a help text listing the usage and description of each command
+    fn help() -> String;
+}
+
+/// A tokenizer for command arguments. Arguments are separated by whitespace, arguments in double
+/// quotes may contain whitespace and escaped characters.
+pub struct Args<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a str) -> Args<'a> {
+        Args { rest: args.trim() }
+    }
+
+    /// Returns the next argument or None if all arguments are consumed
+    pub fn next_arg(&mut self) -> Result<Option<String>, String> {
+        let rest = self.rest.trim_left();
+
+        if rest.is_empty() {
+            self.rest = rest;
+            return Ok(None);
+        }
+
+        if !rest.starts_with('"') {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            self.rest = &rest[end..];
+
+            return Ok(Some(rest[..end].to_string()));
+        }
+
+        let mut arg = String::new();
+        let mut chars = rest[1..].char_indices();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, c)) => arg.push(c),
+                    None => break,
+                },
+                '"' => {
+                    // skip the opening and closing quote
+                    self.rest = &rest[idx + 2..];
+                    return Ok(Some(arg));
+                }
+                c => arg.push(c),
+            }
+        }
+
+        Err("Missing closing quote".into())
+    }
+
+    /// Parses the next argument, which must be present
+    pub fn required<T: FromStr>(&mut self, name: &str) -> Result<T, String> {
+        match self.next_arg()? {
+            Some(arg) => arg.parse()
+                .map_err(|_| format!("Invalid value \"{}\" for argument `{}`", arg, name)),
+            None => Err(format!("Missing argument `{}`", name)),
+        }
+    }
+
+    /// Parses the next argument if there is one left
+    pub fn optional<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+        match self.next_arg()? {
+            Some(arg) => arg.parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid value \"{}\" for argument `{}`", arg, name)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the remaining text or None if nothing is left
+    pub fn rest(&mut self) -> Option<String> {
+        let rest = self.rest.trim();
+        self.rest = "";
+
+        if rest.is_empty() {
+            None
+        } else {
+            Some(rest.to_string())
+        }
+    }
+
+    /// Returns the remaining text, which must not be empty
+    pub fn required_rest(&mut self, name: &str) -> Result<String, String> {
+        self.rest()
+            .ok_or_else(|| format!("Missing argument `{}`", name))
+    }
+
+    /// Checks that all arguments were consumed
+    pub fn finish(&self) -> Result<(), String> {
+        if self.rest.trim().is_empty() {
+            Ok(())
+        } else {
+            Err("Too many arguments".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use objects::Integer;
+
+    fn entity(kind: &str, offset: Integer, length: Integer) -> MessageEntity {
+        MessageEntity {
+            kind: kind.into(),
+            offset,
+            length,
+            url: None,
+            user: None,
+        }
+    }
+
+    fn command(text: &str, length: Integer) -> Option<Command> {
+        Command::parse(text, &[entity("bot_command", 0, length)])
+    }
+
+    #[test]
+    fn parses_name_and_args() {
+        let cmd = command("/start  some args ", 6).unwrap();
+
+        assert_eq!(cmd.name, "/start");
+        assert_eq!(cmd.bot_name, None);
+        assert_eq!(cmd.args, "some args");
+
+        assert_eq!(command("/start", 6).unwrap().args, "");
+    }
+
+    #[test]
+    fn parses_bot_name() {
+        let cmd = command("/start@MyBot hello", 12).unwrap();
+
+        assert_eq!(cmd.name, "/start");
+        assert_eq!(cmd.bot_name, Some("MyBot".into()));
+        assert_eq!(cmd.args, "hello");
+    }
+
+    #[test]
+    fn requires_a_command_at_the_start() {
+        assert_eq!(Command::parse("/start", &[]), None);
+        assert_eq!(Command::parse("/start", &[entity("bold", 0, 6)]), None);
+        assert_eq!(Command::parse("hi /start", &[entity("bot_command", 3, 6)]), None);
+
+        let entities = [entity("bold", 0, 2), entity("bot_command", 0, 6)];
+        assert_eq!(Command::parse("/start", &entities).unwrap().name, "/start");
+    }
+
+    #[test]
+    fn counts_utf16_units() {
+        // the emoji takes two UTF-16 units and four bytes
+        let cmd = command("/pay😀@bot 5 €", 10).unwrap();
+
+        assert_eq!(cmd.name, "/pay😀");
+        assert_eq!(cmd.bot_name, Some("bot".into()));
+        assert_eq!(cmd.args, "5 €");
+
+        // the entity ends within the emoji or after the text
+        assert_eq!(command("/pay😀", 5), None);
+        assert_eq!(command("/pay😀", 7), None);
+    }
+
+    #[test]
+    fn converts_utf16_offsets() {
+        assert_eq!(utf16_to_byte_offset("abc", 0), Some(0));
+        assert_eq!(utf16_to_byte_offset("abc", 3), Some(3));
+        assert_eq!(utf16_to_byte_offset("abc", 4), None);
+
+        assert_eq!(utf16_to_byte_offset("a😀b", 1), Some(1));
+        assert_eq!(utf16_to_byte_offset("a😀b", 2), None);
+        assert_eq!(utf16_to_byte_offset("a😀b", 3), Some(5));
+        assert_eq!(utf16_to_byte_offset("a😀b", 4), Some(6));
+
+        assert_eq!(utf16_to_byte_offset("ä€", 1), Some(2));
+        assert_eq!(utf16_to_byte_offset("ä€", 2), Some(5));
+    }
+
+    #[test]
+    fn addressed_to_bot_names() {
+        let cmd = command("/start@MyBot", 12).unwrap();
+
+        assert!(cmd.is_addressed_to(Some("mybot")));
+        assert!(cmd.is_addressed_to(Some("@MYBOT")));
+        assert!(cmd.is_addressed_to(None));
+        assert!(!cmd.is_addressed_to(Some("otherbot")));
+
+        let cmd = command("/start", 6).unwrap();
+
+        assert!(cmd.is_addressed_to(Some("mybot")));
+        assert!(cmd.is_addressed_to(None));
+    }
+
+    fn split(args: &str) -> Result<Vec<String>, String> {
+        let mut args = Args::new(args);
+        let mut split = Vec::new();
+
+        while let Some(arg) = args.next_arg()? {
+            split.push(arg);
+        }
+
+        Ok(split)
+    }
+
+    #[test]
+    fn splits_args_at_whitespace() {
+        assert_eq!(split("").unwrap(), Vec::<String>::new());
+        assert_eq!(split("  a  b\tc\n d ").unwrap(), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn quoted_args() {
+        assert_eq!(
+            split(r#"say "hello world" "" x"#).unwrap(),
+            vec!["say", "hello world", "", "x"]
+        );
+        assert_eq!(
+            split(r#""a \"quote\"" "back\\slash""#).unwrap(),
+            vec![r#"a "quote""#, r"back\slash"]
+        );
+
+        // quotes only start an argument at its beginning
+        assert_eq!(split(r#"it"s"#).unwrap(), vec![r#"it"s"#]);
+    }
+
+    #[test]
+    fn missing_closing_quote() {
+        assert!(split(r#"a "b c"#).is_err());
+        assert!(split(r#""escaped end\""#).is_err());
+        assert!(split(r#""trailing backslash\"#).is_err());
+    }
+
+    #[test]
+    fn typed_args() {
+        let mut args = Args::new("3 x");
+
+        assert_eq!(args.required::<u32>("count"), Ok(3));
+        assert!(args.required::<u32>("size").is_err());
+        assert!(args.required::<u32>("size").is_err());
+        assert_eq!(args.optional::<u32>("size"), Ok(None));
+    }
+
+    #[test]
+    fn rest_of_the_args() {
+        // a `#[rest] text: Option<String>` field
+        let mut args = Args::new(r#"alice  hello   "dear" friend "#);
+
+        assert_eq!(args.required::<String>("name"), Ok("alice".into()));
+        assert_eq!(args.rest(), Some(r#"hello   "dear" friend"#.into()));
+        assert_eq!(args.rest(), None);
+        assert_eq!(args.finish(), Ok(()));
+
+        // a `#[rest] text: String` field
+        let mut args = Args::new("alice   ");
+
+        assert_eq!(args.required::<String>("name"), Ok("alice".into()));
+        assert!(args.required_rest("text").is_err());
+    }
+
+    #[test]
+    fn too_many_args() {
+        let mut args = Args::new("1 2");
+
+        assert_eq!(args.required::<u32>("a"), Ok(1));
+        assert_eq!(args.finish(), Err("Too many arguments".into()));
+
+        assert_eq!(args.optional::<u32>("b"), Ok(Some(2)));
+        assert_eq!(args.finish(), Ok(()));
+    }
+
+    #[test]
+    fn parses_captions() {
+        let msg: Message = serde_json::from_str(
+            r#"{
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": 2, "type": "private" },
+                "caption": "/upload@bot holiday",
+                "caption_entities": [{ "type": "bot_command", "offset": 0, "length": 11 }]
+            }"#,
+        ).unwrap();
+
+        let cmd = Command::from_message(&msg).unwrap();
+
+        assert_eq!(cmd.name, "/upload");
+        assert_eq!(cmd.bot_name, Some("bot".into()));
+        assert_eq!(cmd.args, "holiday");
+    }
+
+    #[test]
+    fn admin_commands_are_hidden() {
+        let msg: Message = serde_json::from_str(
+            r#"{
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": -5, "type": "supergroup" },
+                "from": { "id": 3, "first_name": "Alice" },
+                "text": "/help"
+            }"#,
+        ).unwrap();
+
+        let visible = |scope| CommandOptions::new().scope(scope).visible_in(&msg);
+
+        assert!(CommandOptions::new().visible_in(&msg));
+        assert!(visible(BotCommandScope::AllGroupChats));
+        assert!(visible(BotCommandScope::Chat { chat_id: -5 }));
+        assert!(!visible(BotCommandScope::AllPrivateChats));
+        assert!(!visible(BotCommandScope::AllChatAdministrators));
+        assert!(!visible(BotCommandScope::ChatAdministrators { chat_id: -5 }));
+    }
+
+    #[test]
+    fn ignores_messages_without_entities() {
+        let msg: Message = serde_json::from_str(
+            r#"{
+                "message_id": 1,
+                "date": 0,
+                "chat": { "id": 2, "type": "private" },
+                "text": "/start"
+            }"#,
+        ).unwrap();
+
+        assert_eq!(Command::from_message(&msg), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Deep links which start the bot with a payload
+//!
+//! A link like `https://t.me/<bot>?start=<payload>` opens the chat with the bot and sends
+//! `/start <payload>` once the user presses start. Telegram only allows up to 64 characters of
+//! `A-Z`, `a-z`, `0-9`, `_` and `-` in the payload, so the payload is base64url encoded. The
+//! encoded payload starts with a marker, which tells it apart from a plain argument of `/start`
+//! that happens to be valid base64url.
+//!
+//! ```rust,ignore
+//! let link = bot.deep_link("ref-1234")?;
+//!
+//! let handle = bot.start_cmd().and_then(|(bot, msg, payload)| {
+//!     if let Some(referrer) = payload {
+//!         ...
+//!     }
+//! });
+//! ```
+
+use base64;
+use failure::{Error, ResultExt};
+
+use error::ErrorKind;
+
+/// The maximal length of an encoded payload
+pub const MAX_PAYLOAD_LEN: usize = 64;
+
+/// The marker in front of every encoded payload
+pub const PAYLOAD_PREFIX: &str = "tb-";
+
+/// Encodes a payload for a deep link, fails if the encoded payload is too long
+pub fn encode_payload(payload: &str) -> Result<String, Error> {
+    let encoded = format!(
+        "{}{}",
+        PAYLOAD_PREFIX,
+        base64::encode_config(payload.as_bytes(), base64::URL_SAFE_NO_PAD)
+    );
+
+    if encoded.len() > MAX_PAYLOAD_LEN {
+        return Err(Error::from(ErrorKind::PayloadTooLong));
+    }
+
+    Ok(encoded)
+}
+
+/// Decodes the payload of a deep link, fails with `PayloadNotEncoded` if the payload lacks the
+/// marker of `encode_payload`, e.g. when the user typed `/start` with an argument
+pub fn decode_payload(payload: &str) -> Result<String, Error> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(Error::from(ErrorKind::PayloadTooLong));
+    }
+
+    if !payload.starts_with(PAYLOAD_PREFIX) {
+        return Err(Error::from(ErrorKind::PayloadNotEncoded));
+    }
+
+    let decoded = base64::decode_config(&payload[PAYLOAD_PREFIX.len()..], base64::URL_SAFE_NO_PAD)
+        .context(ErrorKind::PayloadDecode)?;
+
+    Ok(String::from_utf8(decoded).context(ErrorKind::UTF8Decode)?)
+}
+
+/// Builds a link which starts a private chat with the bot, the name may start with an "@"
+pub fn start_link(bot_name: &str, payload: &str) -> Result<String, Error> {
+    Ok(format!(
+        "https://t.me/{}?start={}",
+        bot_name.trim_left_matches('@'),
+        encode_payload(payload)?
+    ))
+}
+
+/// Builds a link which lets the user add the bot to a group, the name may start with an "@"
+pub fn startgroup_link(bot_name: &str, payload: &str) -> Result<String, Error> {
+    Ok(format!(
+        "https://t.me/{}?startgroup={}",
+        bot_name.trim_left_matches('@'),
+        encode_payload(payload)?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(e: &Error) -> Option<ErrorKind> {
+        e.downcast_ref::<ErrorKind>().cloned()
+    }
+
+    #[test]
+    fn round_trip() {
+        for payload in &["", "ref-1234", "a b/c?d=e&f", "äöü 🙂"] {
+            let encoded = encode_payload(payload).unwrap();
+            assert_eq!(decode_payload(&encoded).unwrap(), *payload);
+        }
+    }
+
+    #[test]
+    fn allowed_alphabet() {
+        let encoded = encode_payload("?>~ÿ\u{0}\u{ff}🙂").unwrap();
+
+        assert!(encoded.starts_with(PAYLOAD_PREFIX));
+        assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        );
+    }
+
+    #[test]
+    fn payload_limit() {
+        // the marker and the encoding leave room for 45 bytes
+        let longest = "x".repeat(45);
+        let encoded = encode_payload(&longest).unwrap();
+        assert!(encoded.len() <= MAX_PAYLOAD_LEN);
+        assert_eq!(decode_payload(&encoded).unwrap(), longest);
+
+        let err = encode_payload(&"x".repeat(46)).unwrap_err();
+        assert_eq!(kind(&err), Some(ErrorKind::PayloadTooLong));
+
+        let err = decode_payload(&format!("{}{}", PAYLOAD_PREFIX, "A".repeat(62))).unwrap_err();
+        assert_eq!(kind(&err), Some(ErrorKind::PayloadTooLong));
+    }
+
+    #[test]
+    fn raw_payload() {
+        // valid base64url, but typed by the user
+        let err = decode_payload("hello").unwrap_err();
+        assert_eq!(kind(&err), Some(ErrorKind::PayloadNotEncoded));
+
+        assert!(decode_payload("tb-a").is_err());
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            start_link("@my_bot", "ref").unwrap(),
+            "https://t.me/my_bot?start=tb-cmVm"
+        );
+        assert_eq!(
+            startgroup_link("my_bot", "ref").unwrap(),
+            "https://t.me/my_bot?startgroup=tb-cmVm"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+//! Multi-step conversations with a state for each user in a chat
+//!
+//! A dialogue stores the current state of every (chat, user) pair which is in a conversation.
+//! Messages of these users are taken out of the normal dispatch and passed to the handler of
+//! their current state, which resolves to the next state or None to end the conversation.
+//!
+//! The messages of a user are handled one after another. A message which arrives while the
+//! handler of the previous one is running waits for the next state, it is dropped if the
+//! conversation ends instead.
+//!
+//! Conversations which timed out are reset when the user writes again and by a sweep which runs
+//! once a minute after the dialogue was added to a bot.
+//!
+//! ```rust,ignore
+//! #[derive(Clone, PartialEq, Eq, Hash)]
+//! enum Signup { AskName, AskAge }
+//!
+//! let dialogue = Dialogue::new()
+//!     .timeout(Duration::from_secs(300))
+//!     .cancel_command("/cancel")
+//!     .on(Signup::AskName, |bot, msg| {
+//!         bot.message(msg.chat.id, "How old are you?".into()).send()
+//!             .map(|_| Some(Signup::AskAge))
+//!     })
+//!     .on(Signup::AskAge, |bot, msg| {
+//!         bot.message(msg.chat.id, "Thanks!".into()).send().map(|_| None)
+//!     });
+//!
+//! bot.add_dialogue(&dialogue);
+//!
+//! // start the conversation from a command
+//! let handle = bot.new_cmd("/signup").and_then(move |(bot, msg)| {
+//!     dialogue.enter(msg.chat.id, msg.from.unwrap().id, Signup::AskName);
+//!     bot.message(msg.chat.id, "What's your name?".into()).send()
+//! });
+//! ```
+
+use std::{hash::Hash, rc::Rc, cell::{Cell, RefCell}, collections::{HashMap, VecDeque},
+          time::{Duration, Instant}};
+
+use failure::Error;
+use futures::{Future, IntoFuture};
+
+use bot::{Interceptor, RcBot};
+use command::Command;
+use functions::FunctionMessage;
+use objects::{Integer, Message};
+use queue::ChatItem;
+
+/// How often conversations which timed out are reset, in seconds
+pub const EXPIRE_INTERVAL: u64 = 60;
+
+type StateHandler<S> = Box<Fn(RcBot, Message) -> Box<Future<Item = Option<S>, Error = Error>>>;
+
+/// A conversation whose handler is running
+struct Running {
+    /// The chat of the conversation, which changes if the group is migrated meanwhile
+    chat_id: Rc<Cell<Integer>>,
+    /// The messages which arrived while the handler was running
+    queued: VecDeque<Message>,
+}
+
+struct DialogueInner<S> {
+    states: RefCell<HashMap<(Integer, Integer), (S, Instant)>>,
+    busy: RefCell<HashMap<(Integer, Integer), Running>>,
+    handlers: RefCell<HashMap<S, StateHandler<S>>>,
+    timeout: Cell<Option<Duration>>,
+    cancel_command: RefCell<Option<String>>,
+    cancel_reply: RefCell<Option<String>>,
+}
+
+/// A conversation state machine, cloning it returns a handle to the same dialogue
+pub struct Dialogue<S> {
+    inner: Rc<DialogueInner<S>>,
+}
+
+impl<S> Clone for Dialogue<S> {
+    fn clone(&self) -> Self {
+        Dialogue {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> Dialogue<S>
+where
+    S: Clone + Eq + Hash + 'static,
+{
+    pub fn new() -> Dialogue<S> {
+        Dialogue {
+            inner: Rc::new(DialogueInner {
+                states: RefCell::new(HashMap::new()),
+                busy: RefCell::new(HashMap::new()),
+                handlers: RefCell::new(HashMap::new()),
+                timeout: Cell::new(None),
+                cancel_command: RefCell::new(None),
+                cancel_reply: RefCell::new(None),
+            }),
+        }
+    }
+
+    /// Resets conversations which had no activity for the given duration
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.inner.timeout.set(Some(timeout));
+
+        self
+    }
+
+    /// Sets a command which ends the conversation of the user, e.g. "/cancel"
+    pub fn cancel_command(self, cmd: &str) -> Self {
+        let cmd = if cmd.starts_with("/") {
+            cmd.into()
+        } else {
+            format!("/{}", cmd)
+        };
+
+        *self.inner.cancel_command.borrow_mut() = Some(cmd);
+
+        self
+    }
+
+    /// Sets a text which is sent when a conversation was cancelled
+    pub fn cancel_reply(self, text: &str) -> Self {
+        *self.inner.cancel_reply.borrow_mut() = Some(text.into());
+
+        self
+    }
+
+    /// Sets the handler for a state. The returned future resolves to the next state, or None if
+    /// the conversation is finished.
+    pub fn on<F, R>(self, state: S, handler: F) -> Self
+    where
+        F: Fn(RcBot, Message) -> R + 'static,
+        R: IntoFuture<Item = Option<S>, Error = Error> + 'static,
+    {
+        self.inner.handlers.borrow_mut().insert(
+            state,
+            Box::new(move |bot, msg| Box::new(handler(bot, msg).into_future())),
+        );
+
+        self
+    }
+
+    /// Starts a conversation or moves it to another state
+    pub fn enter(&self, chat_id: Integer, user_id: Integer, state: S) {
+        self.inner
+            .states
+            .borrow_mut()
+            .insert((chat_id, user_id), (state, Instant::now()));
+    }
+
+    /// Ends the conversation of a user
+    pub fn exit(&self, chat_id: Integer, user_id: Integer) {
+        self.inner.states.borrow_mut().remove(&(chat_id, user_id));
+    }
+
+    /// Returns the current state of a user, stale conversations are reset
+    pub fn current(&self, chat_id: Integer, user_id: Integer) -> Option<S> {
+        let key = (chat_id, user_id);
+        let mut states = self.inner.states.borrow_mut();
+
+        let expired = match (states.get(&key), self.inner.timeout.get()) {
+            (Some(&(_, last_active)), Some(timeout)) => last_active.elapsed() > timeout,
+            _ => false,
+        };
+
+        if expired {
+            debug!("Conversation of user {} in chat {} timed out", user_id, chat_id);
+            states.remove(&key);
+        }
+
+        states.get(&key).map(|&(ref state, _)| state.clone())
+    }
+
+    /// Resets all conversations which had no activity for the timeout. Conversations whose
+    /// handler is running are kept.
+    pub fn expire(&self) {
+        let timeout = match self.inner.timeout.get() {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        let busy = self.inner.busy.borrow();
+        self.inner.states.borrow_mut().retain(|key, &mut (_, last_active)| {
+            busy.contains_key(key) || last_active.elapsed() <= timeout
+        });
+    }
+
+    /// Passes a message to the handler of the state and moves to the next state afterwards
+    fn run(&self, bot: RcBot, chat: Rc<Cell<Integer>>, user_id: Integer, state: S, msg: Message) {
+        // refresh the activity of the conversation
+        self.enter(chat.get(), user_id, state.clone());
+
+        let origin = msg.origin();
+        let fut = (self.inner.handlers.borrow()[&state])(bot.clone(), msg);
+        let dialogue = self.clone();
+        let handle = bot.inner.handle.clone();
+
+        handle.spawn(fut.then(move |result| -> Result<(), ()> {
+            let chat_id = chat.get();
+
+            match result {
+                // don't overwrite the state if the conversation was changed in the meantime
+                Ok(next) => if dialogue.current(chat_id, user_id).as_ref() == Some(&state) {
+                    match next {
+                        Some(next) => dialogue.enter(chat_id, user_id, next),
+                        None => dialogue.exit(chat_id, user_id),
+                    }
+                },
+                Err(e) => bot.report_error(e, origin),
+            }
+
+            dialogue.run_queued(bot, chat, user_id);
+
+            Ok(())
+        }));
+    }
+
+    /// Handles the next message which arrived while the handler was running. Messages for a
+    /// conversation which ended in the meantime are dropped.
+    fn run_queued(&self, bot: RcBot, chat: Rc<Cell<Integer>>, user_id: Integer) {
+        let chat_id = chat.get();
+        let key = (chat_id, user_id);
+
+        loop {
+            // a conversation which was cancelled and started again belongs to another handler
+            let next = match self.inner.busy.borrow_mut().get_mut(&key) {
+                Some(running) => if Rc::ptr_eq(&running.chat_id, &chat) {
+                    Some(running.queued.pop_front())
+                } else {
+                    None
+                },
+                None => None,
+            };
+
+            let msg = match next {
+                Some(Some(msg)) => msg,
+                Some(None) => {
+                    self.inner.busy.borrow_mut().remove(&key);
+                    return;
+                }
+                // the conversation was cancelled
+                None => return,
+            };
+
+            match self.current(chat_id, user_id) {
+                Some(ref state) if self.inner.handlers.borrow().contains_key(state) => {
+                    return self.run(bot, chat, user_id, state.clone(), msg);
+                }
+                _ => debug!(
+                    "Dropped message {} of user {}, the conversation ended",
+                    msg.message_id, user_id
+                ),
+            }
+        }
+    }
+
+    /// Checks whether the message is the cancel command
+    fn is_cancel(&self, bot: &RcBot, msg: &Message) -> bool {
+        let cancel = self.inner.cancel_command.borrow();
+
+        match (cancel.as_ref(), Command::from_message(msg)) {
+            (Some(cancel), Some(command)) => {
+                command.name == *cancel
+                    && command.is_addressed_to(bot.inner.name.borrow().as_ref().map(String::as_str))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<S> Interceptor for Dialogue<S>
+where
+    S: Clone + Eq + Hash + 'static,
+{
+    fn intercept(&self, bot: &RcBot, msg: Message) -> Option<Message> {
+        let (chat_id, user_id) = match msg.from {
+            Some(ref user) => (msg.chat.id, user.id),
+            None => return Some(msg),
+        };
+
+        let state = match self.current(chat_id, user_id) {
+            Some(state) => state,
+            None => return Some(msg),
+        };
+
+        if self.is_cancel(bot, &msg) {
+            self.exit(chat_id, user_id);
+            self.inner.busy.borrow_mut().remove(&(chat_id, user_id));
+
+            if let Some(ref text) = *self.inner.cancel_reply.borrow() {
+                bot.inner.handle.spawn(
+                    bot.message(chat_id, text.clone())
+                        .send()
+                        .map(|_| ())
+                        .map_err(|e| error!("Couldn't send the cancel reply: {}", e)),
+                );
+            }
+
+            return None;
+        }
+
+        if !self.inner.handlers.borrow().contains_key(&state) {
+            warn!("No handler for the current state of the dialogue");
+            return Some(msg);
+        }
+
+        // the message waits until the handler of the previous message resolved the next state
+        if let Some(running) = self.inner.busy.borrow_mut().get_mut(&(chat_id, user_id)) {
+            running.queued.push_back(msg);
+            return None;
+        }
+
+        let chat = Rc::new(Cell::new(chat_id));
+        self.inner.busy.borrow_mut().insert(
+            (chat_id, user_id),
+            Running {
+                chat_id: chat.clone(),
+                queued: VecDeque::new(),
+            },
+        );
+        self.run(bot.clone(), chat, user_id, state, msg);
+
+        None
+    }
+
+    fn migrate_chat(&self, from: Integer, to: Integer) {
+        let mut states = self.inner.states.borrow_mut();
+
+        let keys = states
+            .keys()
+            .filter(|&&(chat_id, _)| chat_id == from)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for (chat_id, user_id) in keys {
+            if let Some(state) = states.remove(&(chat_id, user_id)) {
+                states.insert((to, user_id), state);
+            }
+        }
+
+        // running handlers finish under the new chat, together with the messages they queued
+        let mut busy = self.inner.busy.borrow_mut();
+
+        let keys = busy.keys()
+            .filter(|&&(chat_id, _)| chat_id == from)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for (chat_id, user_id) in keys {
+            if let Some(running) = busy.remove(&(chat_id, user_id)) {
+                running.chat_id.set(to);
+                busy.insert((to, user_id), running);
+            }
+        }
+    }
+}
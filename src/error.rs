@@ -2,6 +2,8 @@ use std::fmt;
 
 use failure::{Backtrace, Context, Fail};
 
+use objects::{Integer, ResponseParameter};
+
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
@@ -89,6 +91,12 @@ pub enum ErrorKind {
     #[fail(display = "Failed to create the interval timer")]
     IntervalTimer,
 
+    #[fail(display = "Failed to create a timer")]
+    Timer,
+
+    #[fail(display = "Invalid cron expression")]
+    Cron,
+
     #[fail(display = "Tokio library caused error")]
     Tokio,
 
@@ -98,6 +106,41 @@ pub enum ErrorKind {
     #[fail(display = "Expected JSON to be a Map, got something else")]
     JsonNotMap,
 
+    // indicates that an encoded deep link payload exceeds the limit of Telegram
+    #[fail(display = "The deep link payload is longer than 64 characters")]
+    PayloadTooLong,
+
+    #[fail(display = "The deep link payload isn't valid base64url")]
+    PayloadDecode,
+
+    // indicates a plain start argument, which wasn't created by a deep link of the bot
+    #[fail(display = "The deep link payload wasn't encoded by the bot")]
+    PayloadNotEncoded,
+
+    // indicates that the username of the bot wasn't resolved yet
+    #[fail(display = "The name of the bot is unknown")]
+    NoBotName,
+
+    // indicates that the data of a callback button exceeds the limit of Telegram
+    #[fail(display = "The callback data is longer than 64 bytes")]
+    CallbackDataTooLong,
+
+    // indicates that a handler stream failed with an error which isn't a `failure::Error`
+    #[fail(display = "A registered handler failed")]
+    Handler,
+
+    // indicates a scheduled call whose timestamp can't be represented
+    #[fail(display = "The due time of a scheduled call is out of range")]
+    InvalidDueTime,
+
+    // indicates a throttle rate without capacity or period
+    #[fail(display = "The capacity and the period of a rate have to be above zero")]
+    InvalidRate,
+
+    // indicates a paginator name which can't be told apart in the callback data
+    #[fail(display = "The name of a paginator must not contain a colon")]
+    InvalidPaginatorName,
+
     // indicates an unknown error
     #[fail(display = "Unknown error")]
     Unknown,
@@ -107,10 +150,115 @@ pub enum ErrorKind {
 #[fail(display = "{}", message)]
 pub struct TelegramError {
     message: String,
+    code: Option<Integer>,
+    parameters: Option<ResponseParameter>,
 }
 
 impl TelegramError {
     pub fn new(message: String) -> Self {
-        TelegramError { message }
+        TelegramError {
+            message,
+            code: None,
+            parameters: None,
+        }
+    }
+
+    /// Creates the error together with the parameters Telegram sent to explain it
+    pub fn with_parameters(message: String, parameters: Option<ResponseParameter>) -> Self {
+        TelegramError {
+            message,
+            code: None,
+            parameters,
+        }
+    }
+
+    /// Sets the error code which Telegram sent, it follows the HTTP status codes
+    pub fn with_code(mut self, code: Option<Integer>) -> Self {
+        self.code = code;
+
+        self
+    }
+
+    /// The error code of the response, e.g. 400 for a bad request or 502 if the servers of
+    /// Telegram are unavailable
+    pub fn code(&self) -> Option<Integer> {
+        self.code
+    }
+
+    /// Searches the causes of an error for a Telegram error
+    pub fn find(error: &::failure::Error) -> Option<&TelegramError> {
+        error
+            .causes()
+            .filter_map(|cause| cause.downcast_ref::<TelegramError>())
+            .next()
+    }
+
+    /// The description which Telegram sent with the error
+    pub fn description(&self) -> &str {
+        &self.message
+    }
+
+    /// Checks whether an edit failed because the new content equals the current one
+    pub fn is_not_modified(&self) -> bool {
+        self.message.contains("message is not modified")
+    }
+
+    /// The new id of a group which was upgraded to a supergroup
+    pub fn migrate_to_chat_id(&self) -> Option<Integer> {
+        self.parameters
+            .as_ref()
+            .and_then(|parameters| parameters.migrate_to_chat_id)
+    }
+
+    /// The number of seconds to wait before the request can be repeated
+    pub fn retry_after(&self) -> Option<Integer> {
+        self.parameters
+            .as_ref()
+            .and_then(|parameters| parameters.retry_after)
+    }
+}
+
+/// Indicates that the arguments of a command couldn't be parsed
+#[derive(Debug, Clone, Fail)]
+#[fail(display = "{}\nUsage: {}", message, usage)]
+pub struct UsageError {
+    pub command: String,
+    pub usage: String,
+    pub message: String,
+}
+
+impl UsageError {
+    pub fn new<S: Into<String>>(command: &str, usage: &str, message: S) -> Self {
+        UsageError {
+            command: command.into(),
+            usage: usage.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// The chat, user and message of the item whose handler failed
+#[derive(Debug, Clone, Default)]
+pub struct ErrorOrigin {
+    pub chat_id: Option<Integer>,
+    pub user_id: Option<Integer>,
+    pub message_id: Option<Integer>,
+    pub callback_query_id: Option<String>,
+}
+
+/// An error of a handler together with the origin of the item which caused it
+#[derive(Debug, Fail)]
+#[fail(display = "{}", error)]
+pub struct HandlerError {
+    pub origin: ErrorOrigin,
+    pub error: ::failure::Error,
+}
+
+impl HandlerError {
+    pub fn new<E: Into<::failure::Error>>(origin: ErrorOrigin, error: E) -> Self {
+        HandlerError {
+            origin,
+            error: error.into(),
+        }
     }
 }
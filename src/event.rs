@@ -0,0 +1,116 @@
+//! Typed service events of a chat
+//!
+//! Telegram reports changes of a chat, like new members or a new title, as messages with one of
+//! the service fields set. The dispatcher turns these messages into a `ChatEvent` and passes them
+//! to the handlers registered with `RcBot::chat_events`, or one of its shortcuts:
+//!
+//! ```rust,ignore
+//! let handle = bot.member_joins().and_then(|(bot, msg, event)| {
+//!     if let ChatEvent::MemberJoined(user) = event {
+//!         bot.message(msg.chat.id, format!("Welcome {}!", user.first_name)).send()
+//!     }
+//!     ...
+//! });
+//! ```
+//!
+//! A group which is upgraded to a supergroup gets a new chat id. The bot moves the sessions,
+//! jobs and conversations of the old chat to the new id on its own, see `RcBot::migrate_chat`.
+//! Requests which still use the old id are repeated with the new one.
+
+use objects::{Integer, Message, PhotoSize, User};
+
+/// The kind of a service event, used to select the events of a handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    MemberJoined,
+    MemberLeft,
+    TitleChanged,
+    PhotoChanged,
+    PhotoDeleted,
+    MessagePinned,
+    GroupCreated,
+    Migrated,
+}
+
+/// A change of a chat reported by a service message
+#[derive(Clone, Debug)]
+pub enum ChatEvent {
+    /// A user joined or was added to the chat
+    MemberJoined(User),
+    /// A user left or was removed from the chat
+    MemberLeft(User),
+    /// The title of the chat was changed to the new one
+    TitleChanged(String),
+    /// The photo of the chat was changed to the new one
+    PhotoChanged(Vec<PhotoSize>),
+    /// The photo of the chat was deleted
+    PhotoDeleted,
+    /// A message was pinned, the message itself is in `pinned_message` of the service message
+    MessagePinned(Integer),
+    /// The group, supergroup or channel was created
+    GroupCreated,
+    /// The group was upgraded to a supergroup with a new id
+    Migrated { from: Integer, to: Integer },
+}
+
+impl ChatEvent {
+    /// Reads the events from a service message, returns an empty list for ordinary messages.
+    /// There is one event for each user who joined, other service messages have a single event.
+    pub fn from_message(msg: &Message) -> Vec<ChatEvent> {
+        match msg.new_chat_members {
+            Some(ref users) if !users.is_empty() => {
+                return users.iter().cloned().map(ChatEvent::MemberJoined).collect();
+            }
+            _ => {}
+        }
+
+        ChatEvent::single_event(msg).into_iter().collect()
+    }
+
+    fn single_event(msg: &Message) -> Option<ChatEvent> {
+        if let Some(ref user) = msg.new_chat_member {
+            return Some(ChatEvent::MemberJoined(user.clone()));
+        }
+        if let Some(ref user) = msg.left_chat_member {
+            return Some(ChatEvent::MemberLeft(user.clone()));
+        }
+        if let Some(ref title) = msg.new_chat_title {
+            return Some(ChatEvent::TitleChanged(title.clone()));
+        }
+        if let Some(ref photo) = msg.new_chat_photo {
+            return Some(ChatEvent::PhotoChanged(photo.clone()));
+        }
+        if msg.delete_chat_photo.unwrap_or(false) {
+            return Some(ChatEvent::PhotoDeleted);
+        }
+        if let Some(ref pinned) = msg.pinned_message {
+            return Some(ChatEvent::MessagePinned(pinned.message_id));
+        }
+        if msg.group_chat_created.unwrap_or(false) || msg.supergroup_chat_created.unwrap_or(false)
+            || msg.channel_chat_created.unwrap_or(false)
+        {
+            return Some(ChatEvent::GroupCreated);
+        }
+        if let Some(to) = msg.migrate_to_chat_id {
+            return Some(ChatEvent::Migrated {
+                from: msg.chat.id,
+                to,
+            });
+        }
+
+        None
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match *self {
+            ChatEvent::MemberJoined(_) => EventKind::MemberJoined,
+            ChatEvent::MemberLeft(_) => EventKind::MemberLeft,
+            ChatEvent::TitleChanged(_) => EventKind::TitleChanged,
+            ChatEvent::PhotoChanged(_) => EventKind::PhotoChanged,
+            ChatEvent::PhotoDeleted => EventKind::PhotoDeleted,
+            ChatEvent::MessagePinned(_) => EventKind::MessagePinned,
+            ChatEvent::GroupCreated => EventKind::GroupCreated,
+            ChatEvent::Migrated { .. } => EventKind::Migrated,
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Composable filters for handler registration
+//!
+//! A filter decides whether a handler receives an update. Filters can be combined with `and`,
+//! `or` and `not`, and attached to commands with `CommandOptions::filter`, to text handlers with
+//! `RcBot::on_text_with` and to callback handlers with `RcBot::callback_queries_with`.
+//!
+//! ```rust,ignore
+//! use telebot::filter::{self, Filter};
+//!
+//! // only accept photos in private chats from two users
+//! let only_admins = filter::private()
+//!     .and(filter::from_users(vec![1234, 5678]))
+//!     .and(filter::content(filter::ContentType::Photo));
+//! ```
+
+use std::rc::Rc;
+
+use bot::RcBot;
+use objects::{CallbackQuery, Integer, Message, Update, User};
+
+/// Something a filter can be applied to, e.g. a message, a callback query or a whole update
+pub trait Filterable {
+    /// The message this item refers to
+    fn message(&self) -> Option<&Message>;
+
+    /// The user who caused this item
+    fn sender(&self) -> Option<&User>;
+
+    /// The callback query this item refers to
+    fn callback_query(&self) -> Option<&CallbackQuery> {
+        None
+    }
+}
+
+impl Filterable for Message {
+    fn message(&self) -> Option<&Message> {
+        Some(self)
+    }
+
+    fn sender(&self) -> Option<&User> {
+        self.from.as_ref()
+    }
+}
+
+impl Filterable for CallbackQuery {
+    fn message(&self) -> Option<&Message> {
+        self.message.as_ref()
+    }
+
+    fn sender(&self) -> Option<&User> {
+        Some(&self.from)
+    }
+
+    fn callback_query(&self) -> Option<&CallbackQuery> {
+        Some(self)
+    }
+}
+
+impl Filterable for Update {
+    fn message(&self) -> Option<&Message> {
+        self.message
+            .as_ref()
+            .or(self.edited_message.as_ref())
+            .or(self.channel_post.as_ref())
+            .or(self.edited_channel_post.as_ref())
+            .or(self.callback_query.as_ref().and_then(|query| query.message.as_ref()))
+    }
+
+    fn sender(&self) -> Option<&User> {
+        if let Some(ref query) = self.callback_query {
+            return Some(&query.from);
+        }
+        if let Some(ref query) = self.inline_query {
+            return Some(&query.from);
+        }
+        if let Some(ref result) = self.chosen_inline_result {
+            return Some(&result.from);
+        }
+
+        self.message().and_then(|msg| msg.from.as_ref())
+    }
+
+    fn callback_query(&self) -> Option<&CallbackQuery> {
+        self.callback_query.as_ref()
+    }
+}
+
+/// A predicate which decides whether a handler receives an item
+pub trait Filter {
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool;
+
+    /// Both filters have to accept the item
+    fn and<F: Filter>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// One of both filters has to accept the item
+    fn or<F: Filter>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    /// Inverts the filter
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+impl<F> Filter for F
+where
+    F: Fn(&RcBot, &Filterable) -> bool,
+{
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool {
+        self(bot, item)
+    }
+}
+
+/// Checks an optional filter, a missing filter accepts everything
+pub fn passes(filter: &Option<Rc<Filter>>, bot: &RcBot, item: &Filterable) -> bool {
+    filter.as_ref().map(|f| f.check(bot, item)).unwrap_or(true)
+}
+
+/// Accepts an item if both filters accept it
+pub struct And<A, B>(A, B);
+
+impl<A: Filter, B: Filter> Filter for And<A, B> {
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool {
+        self.0.check(bot, item) && self.1.check(bot, item)
+    }
+}
+
+/// Accepts an item if one of both filters accepts it
+pub struct Or<A, B>(A, B);
+
+impl<A: Filter, B: Filter> Filter for Or<A, B> {
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool {
+        self.0.check(bot, item) || self.1.check(bot, item)
+    }
+}
+
+/// Accepts an item if the inner filter rejects it
+pub struct Not<A>(A);
+
+impl<A: Filter> Filter for Not<A> {
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool {
+        !self.0.check(bot, item)
+    }
+}
+
+/// Accepts items from chats of the given kinds, e.g. "private", "group", "supergroup" or
+/// "channel"
+pub struct ChatKind(Vec<&'static str>);
+
+impl Filter for ChatKind {
+    fn check(&self, _: &RcBot, item: &Filterable) -> bool {
+        item.message()
+            .map(|msg| self.0.contains(&msg.chat.kind.as_str()))
+            .unwrap_or(false)
+    }
+}
+
+/// Accepts items from private chats
+pub fn private() -> ChatKind {
+    ChatKind(vec!["private"])
+}
+
+/// Accepts items from groups and supergroups
+pub fn group() -> ChatKind {
+    ChatKind(vec!["group", "supergroup"])
+}
+
+/// Accepts items from channels
+pub fn channel() -> ChatKind {
+    ChatKind(vec!["channel"])
+}
+
+/// Accepts items from chats of the given kind
+pub fn chat_kind(kind: &'static str) -> ChatKind {
+    ChatKind(vec![kind])
+}
+
+/// Accepts items sent by one of the users
+pub struct FromUsers(Vec<Integer>);
+
+impl Filter for FromUsers {
+    fn check(&self, _: &RcBot, item: &Filterable) -> bool {
+        item.sender()
+            .map(|user| self.0.contains(&user.id))
+            .unwrap_or(false)
+    }
+}
+
+/// Accepts items sent by one of the users with the given ids
+pub fn from_users(ids: Vec<Integer>) -> FromUsers {
+    FromUsers(ids)
+}
+
+/// The content of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentType {
+    Text,
+    Audio,
+    Document,
+    Game,
+    Photo,
+    Sticker,
+    Video,
+    Voice,
+    Contact,
+    Location,
+    Venue,
+}
+
+impl ContentType {
+    /// Checks whether the message carries this kind of content
+    pub fn matches(&self, msg: &Message) -> bool {
+        match *self {
+            ContentType::Text => msg.text.is_some(),
+            ContentType::Audio => msg.audio.is_some(),
+            ContentType::Document => msg.document.is_some(),
+            ContentType::Game => msg.game.is_some(),
+            ContentType::Photo => msg.photo.is_some(),
+            ContentType::Sticker => msg.sticker.is_some(),
+            ContentType::Video => msg.video.is_some(),
+            ContentType::Voice => msg.voice.is_some(),
+            ContentType::Contact => msg.contact.is_some(),
+            ContentType::Location => msg.location.is_some(),
+            ContentType::Venue => msg.venue.is_some(),
+        }
+    }
+}
+
+/// Accepts messages with the given kind of content
+pub struct Content(ContentType);
+
+impl Filter for Content {
+    fn check(&self, _: &RcBot, item: &Filterable) -> bool {
+        item.message().map(|msg| self.0.matches(msg)).unwrap_or(false)
+    }
+}
+
+/// Accepts messages with the given kind of content
+pub fn content(kind: ContentType) -> Content {
+    Content(kind)
+}
+
+/// Accepts messages which were forwarded from another user or chat
+pub struct Forwarded;
+
+impl Filter for Forwarded {
+    fn check(&self, _: &RcBot, item: &Filterable) -> bool {
+        item.message()
+            .map(|msg| msg.forward_from.is_some() || msg.forward_from_chat.is_some())
+            .unwrap_or(false)
+    }
+}
+
+/// Accepts messages which were forwarded from another user or chat
+pub fn forwarded() -> Forwarded {
+    Forwarded
+}
+
+/// Accepts messages which are a reply to another message
+pub struct IsReply;
+
+impl Filter for IsReply {
+    fn check(&self, _: &RcBot, item: &Filterable) -> bool {
+        item.message()
+            .map(|msg| msg.reply_to_message.is_some())
+            .unwrap_or(false)
+    }
+}
+
+/// Accepts messages which are a reply to another message
+pub fn is_reply() -> IsReply {
+    IsReply
+}
+
+/// Accepts messages which are a reply to a message of this bot
+pub struct ReplyToBot;
+
+impl Filter for ReplyToBot {
+    fn check(&self, bot: &RcBot, item: &Filterable) -> bool {
+        let name = bot.inner.name.borrow();
+
+        let replied_to = item.message()
+            .and_then(|msg| msg.reply_to_message.as_ref())
+            .and_then(|reply| reply.from.as_ref())
+            .and_then(|user| user.username.as_ref());
+
+        match (name.as_ref(), replied_to) {
+            (Some(name), Some(username)) => name.trim_left_matches('@') == username.as_str(),
+            _ => false,
+        }
+    }
+}
+
+/// Accepts messages which are a reply to a message of this bot
+pub fn reply_to_bot() -> ReplyToBot {
+    ReplyToBot
+}
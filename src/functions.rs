@@ -10,6 +10,7 @@ use futures::Future;
 use erased_serde::Serialize;
 
 use bot::{Bot, RcBot};
+use sync::SyncBot;
 use objects::{self, Integer};
 use file;
 use error::ErrorKind;
@@ -96,14 +97,14 @@ impl From<objects::ForceReply> for ReplyMarkup {
 impl Into<String> for Action {
     fn into(self) -> String {
         let tmp = match self {
-            Action::Typing => "Typing",
-            Action::UploadPhoto => "UploadPhoto",
-            Action::RecordVideo => "RecordVideo",
-            Action::UploadVideo => "UploadVideo",
-            Action::RecordAudio => "RecordVideo",
-            Action::UploadAudio => "UploadAudio",
-            Action::UploadDocument => "UploadDocument",
-            Action::FindLocation => "FindLocation",
+            Action::Typing => "typing",
+            Action::UploadPhoto => "upload_photo",
+            Action::RecordVideo => "record_video",
+            Action::UploadVideo => "upload_video",
+            Action::RecordAudio => "record_audio",
+            Action::UploadAudio => "upload_audio",
+            Action::UploadDocument => "upload_document",
+            Action::FindLocation => "find_location",
         };
 
         tmp.into()
@@ -155,6 +156,19 @@ pub struct Message {
     reply_markup: Option<ReplyMarkup>,
 }
 
+/// Use this method to forward messages of any kind. On success, the sent Message is returned.
+#[derive(TelegramFunction, Serialize)]
+#[call = "forwardMessage"]
+#[answer = "Message"]
+#[function = "forward_message"]
+pub struct ForwardMessage {
+    chat_id: Integer,
+    from_chat_id: Integer,
+    message_id: Integer,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disable_notification: Option<bool>,
+}
+
 /// Use this method to send photos. On success, the sent Message is returned.
 #[derive(TelegramFunction, Serialize)]
 #[call = "sendPhoto"]
@@ -170,7 +184,7 @@ pub struct SendPhoto {
     #[serde(skip_serializing_if = "Option::is_none")]
     disable_notification: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    reply_to_message_id: Option<bool>,
+    reply_to_message_id: Option<Integer>,
     #[serde(skip_serializing_if = "Option::is_none")]
     reply_markup: Option<ReplyMarkup>,
 }
@@ -620,11 +634,19 @@ pub struct GetChat {
 #[derive(TelegramFunction, Serialize)]
 #[call = "getChatAdministrators"]
 #[answer = "Vector<objects::ChatMember>"]
-#[function = "unban_chat_administrators"]
+#[function = "get_chat_administrators"]
 pub struct GetChatAdministrators {
     chat_id: Integer,
 }
 
+impl RcBot {
+    /// The former name of `get_chat_administrators`, which doesn't unban anybody
+    #[deprecated(note = "use get_chat_administrators")]
+    pub fn unban_chat_administrators(&self, chat_id: Integer) -> WrapperGetChatAdministrators {
+        self.get_chat_administrators(chat_id)
+    }
+}
+
 /// Use this method to get the number of members in a chat. Returns Int on success.
 #[derive(TelegramFunction, Serialize)]
 #[call = "getChatMembersCount"]
@@ -803,3 +825,75 @@ pub struct AddStickerToSet {
 pub struct DeleteStickerFromSet {
     sticker: String,
 }
+
+/// Use this method to change the list of the bot's commands. Returns True on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "setMyCommands"]
+#[answer = "Boolean"]
+#[function = "set_my_commands"]
+pub struct SetMyCommands {
+    commands: Vec<objects::BotCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
+
+/// Use this method to get the current list of the bot's commands for the given scope and user
+/// language. Returns Array of BotCommand on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "getMyCommands"]
+#[answer = "Vector<objects::BotCommand>"]
+#[function = "get_my_commands"]
+pub struct GetMyCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
+
+/// Use this method to delete the list of the bot's commands for the given scope and user
+/// language. Returns True on success.
+#[derive(TelegramFunction, Serialize)]
+#[call = "deleteMyCommands"]
+#[answer = "Boolean"]
+#[function = "delete_my_commands"]
+pub struct DeleteMyCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<objects::BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_use_the_names_of_telegram() {
+        let typing: String = Action::Typing.into();
+        let record_audio: String = Action::RecordAudio.into();
+        let upload_document: String = Action::UploadDocument.into();
+
+        assert_eq!(typing, "typing");
+        assert_eq!(record_audio, "record_audio");
+        assert_eq!(upload_document, "upload_document");
+    }
+
+    #[test]
+    fn photo_replies_to_a_message_id() {
+        let photo = SendPhoto {
+            chat_id: 1,
+            photo: Some("file-id".into()),
+            caption: None,
+            disable_notification: None,
+            reply_to_message_id: Some(42),
+            reply_markup: None,
+        };
+
+        let json = serde_json::to_value(&photo).unwrap();
+
+        assert_eq!(json["reply_to_message_id"], 42);
+        assert_eq!(json["chat_id"], 1);
+    }
+}
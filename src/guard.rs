@@ -0,0 +1,183 @@
+//! Guards which restrict handlers to some users
+//!
+//! A guard is attached to the stream of a handler with `HandlerStream::guard`:
+//!
+//! ```rust,ignore
+//! let admins = AdminGuard::new(&bot)
+//!     .ttl(Duration::from_secs(600))
+//!     .reply("Only admins can ban users");
+//!
+//! let handle = bot.new_cmd("/ban").guard(admins).and_then(|(bot, msg)| ...);
+//! ```
+
+use std::{rc::Rc, cell::{Cell, RefCell}, collections::HashMap, time::{Duration, Instant}};
+
+use failure::Error;
+use futures::{future, Future};
+
+use bot::RcBot;
+use functions::{FunctionGetChatAdministrators, FunctionMessage};
+use handler::Guard;
+use objects::Integer;
+use queue::ChatItem;
+
+/// How often the admins of chats whose cache expired are removed
+const EVICT_INTERVAL: u64 = 60;
+
+struct AdminGuardInner {
+    bot: RcBot,
+    admins: RefCell<HashMap<Integer, (Rc<Vec<Integer>>, Instant)>>,
+    ttl: Cell<Duration>,
+    reply: RefCell<Option<String>>,
+    evicted: Cell<Instant>,
+}
+
+/// Only passes items from the admins of a chat. The admins of each chat are fetched with
+/// `getChatAdministrators` and cached for some time, five minutes by default. Items from
+/// private chats are always passed. Cloning the guard shares the cache.
+#[derive(Clone)]
+pub struct AdminGuard {
+    inner: Rc<AdminGuardInner>,
+}
+
+impl AdminGuard {
+    pub fn new(bot: &RcBot) -> AdminGuard {
+        AdminGuard {
+            inner: Rc::new(AdminGuardInner {
+                bot: bot.clone(),
+                admins: RefCell::new(HashMap::new()),
+                ttl: Cell::new(Duration::from_secs(300)),
+                reply: RefCell::new(None),
+                evicted: Cell::new(Instant::now()),
+            }),
+        }
+    }
+
+    /// Sets how long the admins of a chat are cached
+    pub fn ttl(self, ttl: Duration) -> Self {
+        self.inner.ttl.set(ttl);
+
+        self
+    }
+
+    /// Sets a text which is sent as reply to users who aren't admins
+    pub fn reply(self, text: &str) -> Self {
+        *self.inner.reply.borrow_mut() = Some(text.into());
+
+        self
+    }
+
+    /// Removes the cached admins of a chat, e.g. after a user was promoted
+    pub fn invalidate(&self, chat_id: Integer) {
+        self.inner.admins.borrow_mut().remove(&chat_id);
+    }
+
+    /// Removes the admins of the chats whose cache expired. Runs at most once per interval.
+    fn evict(&self) {
+        if self.inner.evicted.get().elapsed() < Duration::from_secs(EVICT_INTERVAL) {
+            return;
+        }
+
+        let ttl = self.inner.ttl.get();
+        self.inner
+            .admins
+            .borrow_mut()
+            .retain(|_, &mut (_, fetched)| fetched.elapsed() < ttl);
+
+        self.inner.evicted.set(Instant::now());
+    }
+
+    /// Resolves to the ids of the admins of a chat
+    pub fn admins(&self, chat_id: Integer) -> Box<Future<Item = Rc<Vec<Integer>>, Error = Error>> {
+        self.evict();
+
+        let cached = match self.inner.admins.borrow().get(&chat_id) {
+            Some(&(ref admins, fetched)) if fetched.elapsed() < self.inner.ttl.get() => {
+                Some(admins.clone())
+            }
+            _ => None,
+        };
+
+        if let Some(admins) = cached {
+            return Box::new(future::ok(admins));
+        }
+
+        let guard = self.clone();
+        let fut = self.inner
+            .bot
+            .get_chat_administrators(chat_id)
+            .send()
+            .map(move |(_, members)| {
+                let admins = Rc::new(members.into_iter().map(|member| member.user.id).collect());
+
+                guard
+                    .inner
+                    .admins
+                    .borrow_mut()
+                    .insert(chat_id, (Rc::clone(&admins), Instant::now()));
+
+                admins
+            });
+
+        Box::new(fut)
+    }
+
+    /// Resolves to true if the user is an admin of the chat
+    pub fn is_admin(
+        &self,
+        chat_id: Integer,
+        user_id: Integer,
+    ) -> Box<Future<Item = bool, Error = Error>> {
+        Box::new(
+            self.admins(chat_id)
+                .map(move |admins| admins.contains(&user_id)),
+        )
+    }
+
+    /// Sends the rejection reply to the chat
+    fn reject(&self, chat_id: Integer, message_id: Option<Integer>) {
+        let text = match *self.inner.reply.borrow() {
+            Some(ref text) => text.clone(),
+            None => return,
+        };
+
+        let mut msg = self.inner.bot.message(chat_id, text);
+        if let Some(id) = message_id {
+            msg = msg.reply_to_message_id(id);
+        }
+
+        self.inner.bot.inner.handle.spawn(
+            msg.send()
+                .map(|_| ())
+                .map_err(|e| error!("Couldn't send the rejection: {}", e)),
+        );
+    }
+}
+
+impl Guard for AdminGuard {
+    fn check(&self, item: &ChatItem) -> Box<Future<Item = bool, Error = Error>> {
+        let origin = item.origin();
+
+        let (chat_id, user_id) = match (origin.chat_id, origin.user_id) {
+            (Some(chat_id), Some(user_id)) => (chat_id, user_id),
+            _ => return Box::new(future::ok(false)),
+        };
+
+        // nobody else is in a private chat
+        if item.chat().map_or(false, |chat| chat.kind == "private") {
+            return Box::new(future::ok(true));
+        }
+
+        let guard = self.clone();
+        let message_id = origin.message_id;
+
+        Box::new(self.is_admin(chat_id, user_id).map(move |admin| {
+            if !admin {
+                debug!("User {} isn't an admin of chat {}", user_id, chat_id);
+                guard.reject(chat_id, message_id);
+            }
+
+            admin
+        }))
+    }
+}
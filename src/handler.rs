@@ -0,0 +1,124 @@
+//! Streams of registered handlers which can be removed again at runtime
+//!
+//! The streams returned by `RcBot::new_cmd`, `RcBot::on_text` and `RcBot::callback_queries` carry
+//! a handle to their registration. The handle stays valid after the stream was consumed by
+//! combinators like `and_then`, so it should be taken before the stream is registered:
+//!
+//! ```rust,ignore
+//! let stream = bot.new_cmd("/vote");
+//! let handle = stream.handle();
+//!
+//! bot.register(stream.and_then(|(bot, msg)| ...));
+//!
+//! // later, e.g. when the vote is closed
+//! handle.unregister();
+//! ```
+//!
+//! Dropping the stream removes the handler as well, the next update which would be sent to it is
+//! passed on as if the handler was never registered.
+//!
+//! A guard decides asynchronously whether an item reaches the handler, e.g. to restrict a command
+//! to the admins of a chat with `guard::AdminGuard`.
+
+use std::rc::Weak;
+
+use failure::Error;
+use futures::{Future, Poll, Stream};
+
+use bot::Bot;
+use queue::ChatItem;
+
+/// An asynchronous check in front of a handler
+pub trait Guard {
+    /// Resolves to true if the item should be passed to the handler
+    fn check(&self, item: &ChatItem) -> Box<Future<Item = bool, Error = Error>>;
+}
+
+/// A handle to a registered handler
+#[derive(Clone)]
+pub struct Handle {
+    bot: Weak<Bot>,
+    id: usize,
+}
+
+impl Handle {
+    pub fn new(bot: Weak<Bot>, id: usize) -> Handle {
+        Handle { bot, id }
+    }
+
+    /// The id of the registration
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Removes the handler, returns false if it was already removed
+    pub fn unregister(&self) -> bool {
+        match self.bot.upgrade() {
+            Some(bot) => bot.remove_handler(self.id),
+            None => false,
+        }
+    }
+}
+
+/// The stream of a registered handler
+pub struct HandlerStream<T> {
+    stream: Box<Stream<Item = T, Error = Error>>,
+    handle: Handle,
+}
+
+impl<T> HandlerStream<T> {
+    pub fn new<S>(stream: S, handle: Handle) -> HandlerStream<T>
+    where
+        S: Stream<Item = T, Error = Error> + 'static,
+    {
+        HandlerStream {
+            stream: Box::new(stream),
+            handle,
+        }
+    }
+
+    /// Returns the handle of the registration
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// Maps the items of the stream and keeps the handle
+    pub fn map_items<U, F>(self, f: F) -> HandlerStream<U>
+    where
+        T: 'static,
+        F: FnMut(T) -> U + 'static,
+    {
+        let handle = self.handle;
+
+        HandlerStream::new(self.stream.map(f), handle)
+    }
+
+    /// Only passes the items to the handler which are accepted by the guard, the items are
+    /// checked one after another
+    pub fn guard<G>(self, guard: G) -> HandlerStream<T>
+    where
+        T: ChatItem + 'static,
+        G: Guard + 'static,
+    {
+        let handle = self.handle;
+
+        let stream = self.stream
+            .and_then(move |item| {
+                guard
+                    .check(&item)
+                    .map(move |passed| if passed { Some(item) } else { None })
+            })
+            .filter_map(|item| item);
+
+        HandlerStream::new(stream, handle)
+    }
+}
+
+impl<T> Stream for HandlerStream<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<T>, Error> {
+        self.stream.poll()
+    }
+}
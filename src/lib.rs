@@ -63,12 +63,15 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+extern crate base64;
+extern crate chrono;
 extern crate erased_serde;
 extern crate futures;
 extern crate hyper;
 extern crate hyper_multipart_rfc7578 as hyper_multipart;
 extern crate hyper_tls;
 extern crate native_tls;
+extern crate regex;
 extern crate serde;
 extern crate serde_json;
 extern crate tokio_core;
@@ -82,7 +85,26 @@ pub use bot::RcBot;
 pub use file::File;
 
 pub mod bot;
+pub mod command;
+pub mod deeplink;
+pub mod dialogue;
 pub mod error;
+pub mod event;
 pub mod objects;
+pub mod paginate;
+pub mod queue;
+pub mod reply;
+pub mod runner;
+pub mod scheduled;
+pub mod scheduler;
+pub mod session;
+pub mod sync;
 pub mod functions;
+pub mod guard;
 pub mod file;
+pub mod filter;
+pub mod handler;
+pub mod middleware;
+pub mod text;
+pub mod throttle;
+pub mod wait;
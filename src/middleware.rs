@@ -0,0 +1,132 @@
+//! Middleware which runs for every update before it is dispatched to the handlers
+//!
+//! A middleware is registered with `RcBot::add_middleware`. The `before` hooks of all middleware
+//! run in the order of registration and may modify the update, attach data to it or stop its
+//! processing. After the update was dispatched, the `after` hooks run in reverse order.
+//!
+//! Data attached to the extensions of an update is moved to the message, callback query or inline
+//! query which is passed to the handler, e.g. `msg.extensions.get::<Locale>()`. Cloning an object
+//! doesn't copy its extensions. If a message is passed to several handlers, e.g. one event for
+//! each user who joined, only the last one receives the extensions.
+
+use std::{fmt, any::{Any, TypeId}, collections::HashMap};
+
+use failure::Error;
+use futures::{future, Future};
+
+use bot::RcBot;
+use objects::{Integer, Update};
+
+/// A hook around the dispatch of every update
+pub trait Middleware {
+    /// Called before the update is dispatched. The returned future resolves to the update which
+    /// is passed on, or None to stop the processing of the update.
+    fn before(
+        &self,
+        bot: &RcBot,
+        update: Update,
+    ) -> Box<Future<Item = Option<Update>, Error = Error>>;
+
+    /// Called after the update was dispatched or stopped by a later middleware
+    fn after(&self, _bot: &RcBot, _update_id: Integer) -> Box<Future<Item = (), Error = Error>> {
+        Box::new(future::ok(()))
+    }
+}
+
+/// A map which holds at most one value of each type. The values have to be `Send`, so that
+/// objects with extensions can be passed to other threads.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<Any + Send>>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        Extensions {
+            map: HashMap::new(),
+        }
+    }
+
+    /// Inserts a value, a previous value of the same type is returned
+    pub fn insert<T: Any + Send>(&mut self, val: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(val))
+            .and_then(|prev| prev.downcast().ok())
+            .map(|prev| *prev)
+    }
+
+    /// Returns a reference to the value of a type
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|val| val.downcast_ref())
+    }
+
+    /// Returns a mutable reference to the value of a type
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|val| val.downcast_mut())
+    }
+
+    /// Removes the value of a type
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|val| val.downcast().ok())
+            .map(|val| *val)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// The values can't be cloned, so a clone starts without extensions
+impl Clone for Extensions {
+    fn clone(&self) -> Extensions {
+        Extensions::new()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Extensions({} entries)", self.map.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    use objects::Message;
+
+    #[derive(Debug, PartialEq)]
+    struct Locale(&'static str);
+
+    #[test]
+    fn clone_starts_empty() {
+        let mut extensions = Extensions::new();
+        extensions.insert(Locale("de"));
+
+        let clone = extensions.clone();
+        assert!(clone.is_empty());
+        assert_eq!(clone.get::<Locale>(), None);
+        assert_eq!(extensions.get::<Locale>(), Some(&Locale("de")));
+    }
+
+    #[test]
+    fn cloned_message_loses_extensions() {
+        let mut msg = serde_json::from_str::<Message>(
+            r#"{ "message_id": 1, "date": 0, "chat": { "id": 2, "type": "private" } }"#,
+        ).unwrap();
+        msg.extensions.insert(Locale("de"));
+
+        let clone = msg.clone();
+        assert_eq!(clone.message_id, msg.message_id);
+        assert!(clone.extensions.is_empty());
+        assert_eq!(msg.extensions.get::<Locale>(), Some(&Locale("de")));
+    }
+}
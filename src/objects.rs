@@ -13,7 +13,9 @@ pub type NotImplemented = ();
 use erased_serde::Serialize;
 use uuid::Uuid;
 
-#[derive(Deserialize, Debug)]
+use middleware::Extensions;
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum EditResponse {
     Message(Message),
@@ -33,7 +35,7 @@ pub struct User {
 }
 
 /// This object represents a chat.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Chat {
     pub id: Integer,
     #[serde(rename = "type")]
@@ -47,7 +49,7 @@ pub struct Chat {
 
 /// This object represents one special entity in a text message. For example, hashtags, usernames,
 /// URLs, etc.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct MessageEntity {
     #[serde(rename = "type")]
     pub kind: String,
@@ -58,7 +60,7 @@ pub struct MessageEntity {
 }
 
 /// This object represents a message.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Message {
     pub message_id: Integer,
     pub from: Option<User>,
@@ -80,9 +82,12 @@ pub struct Message {
     pub video: Option<Video>,
     pub voice: Option<Voice>,
     pub caption: Option<String>,
+    pub caption_entities: Option<Vec<MessageEntity>>,
     pub contact: Option<Contact>,
     pub location: Option<Location>,
     pub venue: Option<Venue>,
+    pub new_chat_members: Option<Vec<User>>,
+    /// Deprecated by Telegram in favour of `new_chat_members`
     pub new_chat_member: Option<User>,
     pub left_chat_member: Option<User>,
     pub new_chat_title: Option<String>,
@@ -94,12 +99,16 @@ pub struct Message {
     pub migrate_to_chat_id: Option<Integer>,
     pub migrate_from_chat_id: Option<Integer>,
     pub pinned_message: Option<Box<Message>>,
+    /// Data attached by middleware, not part of the Telegram object. The values can't be
+    /// cloned, a clone of the object starts with empty extensions.
+    #[serde(skip)]
+    pub extensions: Extensions,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Updates(pub Vec<Update>);
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Update {
     pub update_id: Integer,
     pub message: Option<Message>,
@@ -107,8 +116,12 @@ pub struct Update {
     pub channel_post: Option<Message>,
     pub edited_channel_post: Option<Message>,
     pub inline_query: Option<InlineQuery>,
-    pub chosen_inline_result: Option<()>,
+    pub chosen_inline_result: Option<ChosenInlineResult>,
     pub callback_query: Option<CallbackQuery>,
+    /// Data attached by middleware, not part of the Telegram object. The values can't be
+    /// cloned, a clone of the object starts with empty extensions.
+    #[serde(skip)]
+    pub extensions: Extensions,
 }
 
 /// This object represents one size of a photo or a file / sticker thumbnail.
@@ -121,7 +134,7 @@ pub struct PhotoSize {
 }
 
 /// This object represents an audio file to be treated as music by the Telegram clients.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Audio {
     pub file_id: String,
     pub duration: Integer,
@@ -132,7 +145,7 @@ pub struct Audio {
 }
 
 /// This object represents a general file (as opposed to photos, voice messages and audio files).
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Document {
     pub file_id: String,
     pub thumb: Option<PhotoSize>,
@@ -142,7 +155,7 @@ pub struct Document {
 }
 
 /// This object represents an animation file to be displayed in the message containing a game
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Animation {
     pub file_id: String,
     pub thumb: Option<PhotoSize>,
@@ -151,7 +164,7 @@ pub struct Animation {
     pub file_size: Option<Integer>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Game {
     pub title: String,
     pub description: Option<String>,
@@ -161,7 +174,7 @@ pub struct Game {
     pub animation: Option<Animation>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct GameHighScore {
     pub position: Integer,
     pub user: User,
@@ -169,7 +182,7 @@ pub struct GameHighScore {
 }
 
 /// This object represents a sticker.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Sticker {
     pub file_id: String,
     pub width: Integer,
@@ -181,7 +194,7 @@ pub struct Sticker {
 }
 
 /// This object represents a video file.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Video {
     pub file_id: String,
     pub width: Integer,
@@ -193,7 +206,7 @@ pub struct Video {
 }
 
 /// This object represents a voice note.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Voice {
     pub file_id: String,
     pub duration: Integer,
@@ -202,7 +215,7 @@ pub struct Voice {
 }
 
 /// This object represents a phone contact.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Contact {
     pub phone_number: String,
     pub first_name: String,
@@ -211,14 +224,14 @@ pub struct Contact {
 }
 
 /// This object represents a point on the map.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Location {
     pub longitude: f32,
     pub latitude: f32,
 }
 
 /// This object represents a venue.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Venue {
     pub location: Location,
     pub title: String,
@@ -227,7 +240,7 @@ pub struct Venue {
 }
 
 /// This object represent a user's profile pictures.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct UserProfilePhotos {
     pub total_count: Integer,
     pub photos: Vec<Vec<PhotoSize>>,
@@ -237,7 +250,7 @@ pub struct UserProfilePhotos {
 /// https://api.telegram.org/file/bot<token>/<file_path>. It is guaranteed that the link will be
 /// valid for at least 1 hour. When the link expires, a new one can be requested by calling
 /// getFile.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct File {
     pub file_id: String,
     pub file_size: Option<Integer>,
@@ -246,7 +259,7 @@ pub struct File {
 
 /// This object represents a custom keyboard with reply options (see Introduction to bots for
 /// details and examples).
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ReplyKeyboardMarkup {
     pub keyboard: Vec<Vec<KeyboardButton>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -260,7 +273,7 @@ pub struct ReplyKeyboardMarkup {
 /// This object represents one button of the reply keyboard. For simple text buttons String can be
 /// used instead of this object to specify text of the button. Optional fields are mutually
 /// exclusive.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct KeyboardButton {
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -273,7 +286,7 @@ pub struct KeyboardButton {
 /// keyboard and display the default letter-keyboard. By default, custom keyboards are displayed
 /// until a new keyboard is sent by a bot. An exception is made for one-time keyboards that are
 /// hidden immediately after the user presses a button (see ReplyKeyboardMarkup).
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ReplyKeyboardRemove {
     pub remove_keyboard: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -281,14 +294,14 @@ pub struct ReplyKeyboardRemove {
 }
 
 /// This object represents an inline keyboard that appears right next to the message it belongs to.
-#[derive(setter, Serialize, Deserialize, Debug)]
+#[derive(setter, Serialize, Deserialize, Debug, Clone)]
 pub struct InlineKeyboardMarkup {
     pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
 }
 
 /// This object represents one button of an inline keyboard. You must use exactly one of the
 /// optional fields.
-#[derive(setter, Serialize, Deserialize, Debug)]
+#[derive(setter, Serialize, Deserialize, Debug, Clone)]
 pub struct InlineKeyboardButton {
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -308,7 +321,7 @@ pub struct InlineKeyboardButton {
 /// message will be present. If the button was attached to a message sent via the bot (in inline
 /// mode), the field inline_message_id will be present. Exactly one of the fields data or
 /// game_short_name will be present.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct CallbackQuery {
     pub id: String,
     pub from: User,
@@ -317,13 +330,17 @@ pub struct CallbackQuery {
     pub chat_instance: Option<String>,
     pub data: Option<String>,
     pub game_short_name: Option<String>,
+    /// Data attached by middleware, not part of the Telegram object. The values can't be
+    /// cloned, a clone of the object starts with empty extensions.
+    #[serde(skip)]
+    pub extensions: Extensions,
 }
 
 /// Upon receiving a message with this object, Telegram clients will display a reply interface to
 /// the user (act as if the user has selected the bot‘s message and tapped ’Reply'). This can be
 /// extremely useful if you want to create user-friendly step-by-step interfaces without having to
 /// sacrifice privacy mode.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ForceReply {
     pub force_reply: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -331,32 +348,36 @@ pub struct ForceReply {
 }
 
 /// This object contains information about one member of the chat.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ChatMember {
     pub user: User,
     pub status: String,
 }
 
 /// Contains information about why a request was unsuccessfull.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct ResponseParameter {
     pub migrate_to_chat_id: Option<Integer>,
     pub retry_after: Option<Integer>,
 }
 
 /// A placeholder, currently holds no information. Use BotFather to set up your game.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CallbackGame;
 
 ///This object represents an incoming inline query. When the user sends an empty query, youur bot
 ///could return some default or  trending results.
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct InlineQuery {
     pub id: String,
     pub from: User,
     pub location: Option<Location>,
     pub query: String,
     pub offset: String,
+    /// Data attached by middleware, not part of the Telegram object. The values can't be
+    /// cloned, a clone of the object starts with empty extensions.
+    #[serde(skip)]
+    pub extensions: Extensions,
 }
 
 /*#[derive(Serialize)]
@@ -776,7 +797,7 @@ pub struct InlineQueryResultCachedAudio {
 pub mod input_message_content {
     use super::Boolean;
 
-    #[derive(setter, Serialize, Deserialize, Debug)]
+    #[derive(setter, Serialize, Deserialize, Debug, Clone)]
     pub struct Text {
         pub message_text: String,
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -785,13 +806,13 @@ pub mod input_message_content {
         pub disable_web_page_preview: Option<Boolean>,
     }
 
-    #[derive(setter, Serialize, Deserialize, Debug)]
+    #[derive(setter, Serialize, Deserialize, Debug, Clone)]
     pub struct Location {
         pub latitude: f64,
         pub longitude: f64,
     }
 
-    #[derive(setter, Serialize, Deserialize, Debug)]
+    #[derive(setter, Serialize, Deserialize, Debug, Clone)]
     pub struct Venue {
         pub latitude: f64,
         pub longitude: f64,
@@ -801,7 +822,7 @@ pub mod input_message_content {
         pub foursquare_id: Option<String>,
     }
 
-    #[derive(setter, Serialize, Deserialize, Debug)]
+    #[derive(setter, Serialize, Deserialize, Debug, Clone)]
     pub struct Contact {
         pub phone_number: String,
         pub first_name: String,
@@ -810,13 +831,39 @@ pub mod input_message_content {
     }
 }
 
-#[derive(setter, Serialize, Deserialize, Debug)]
+/// Represents a result of an inline query that was chosen by the user and sent to their chat
+/// partner.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChosenInlineResult {
     pub result_id: String,
     pub from: User,
-    pub offset: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<Location>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub inline_message_id: Option<String>,
+    pub query: String,
+    /// Data attached by middleware, not part of the Telegram object. The values can't be
+    /// cloned, a clone of the object starts with empty extensions.
+    #[serde(skip)]
+    pub extensions: Extensions,
+}
+
+/// This object represents a bot command.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BotCommand {
+    pub command: String,
+    pub description: String,
+}
+
+/// This object represents the scope to which bot commands are applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotCommandScope {
+    Default,
+    AllPrivateChats,
+    AllGroupChats,
+    AllChatAdministrators,
+    Chat { chat_id: Integer },
+    ChatAdministrators { chat_id: Integer },
+    ChatMember { chat_id: Integer, user_id: Integer },
 }
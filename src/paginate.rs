@@ -0,0 +1,247 @@
+//! Long lists which are split into pages
+//!
+//! A paginator sends one page of a list together with an inline keyboard to move to the previous
+//! or the next page. The buttons edit the message in place. Every list is identified by a key,
+//! which is passed to the closure fetching the pages, e.g. the query of a search:
+//!
+//! ```rust,ignore
+//! let results = Paginator::new(&bot, "search", |_, query, index| {
+//!     let hits = search(query);
+//!     let count = (hits.len() + 9) / 10;
+//!
+//!     let page = hits.iter().skip(index * 10).take(10).cloned().collect::<Vec<_>>();
+//!
+//!     Ok(Page::new(page.join("\n"), count))
+//! })?;
+//!
+//! let handle = bot.new_cmd("/search").and_then(move |(_, msg)| {
+//!     results.send(msg.chat.id, &msg.text.unwrap_or_default())
+//! });
+//! ```
+//!
+//! The name, the page and the key are stored in the callback data of the buttons, which is
+//! limited to 64 bytes by Telegram.
+
+use std::rc::Rc;
+
+use failure::Error;
+use futures::{future, Future, IntoFuture};
+
+use bot::RcBot;
+use error::{ErrorKind, TelegramError};
+use filter::Filterable;
+use functions::{FunctionAnswerCallbackQuery, FunctionEditMessageText, FunctionMessage};
+use handler::Handle;
+use objects::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, Integer, Message};
+
+/// One page of a list
+pub struct Page {
+    pub text: String,
+    /// The number of pages of the whole list
+    pub count: usize,
+}
+
+impl Page {
+    pub fn new<S: Into<String>>(text: S, count: usize) -> Page {
+        Page {
+            text: text.into(),
+            count,
+        }
+    }
+}
+
+type Fetch = Fn(&RcBot, &str, usize) -> Box<Future<Item = Page, Error = Error>>;
+
+struct PaginatorInner {
+    bot: RcBot,
+    name: String,
+    fetch: Box<Fetch>,
+    handle: Handle,
+}
+
+/// Sends lists page by page and handles the navigation buttons of its messages
+#[derive(Clone)]
+pub struct Paginator {
+    inner: Rc<PaginatorInner>,
+}
+
+impl Paginator {
+    /// Creates a paginator and registers the handler of its buttons. The name tells the buttons
+    /// of different paginators apart, it fails if the name contains a colon. The closure returns
+    /// a page of a list, the first page has the index zero.
+    pub fn new<F, R>(bot: &RcBot, name: &str, fetch: F) -> Result<Paginator, Error>
+    where
+        F: Fn(&RcBot, &str, usize) -> R + 'static,
+        R: IntoFuture<Item = Page, Error = Error> + 'static,
+        R::Future: 'static,
+    {
+        // the colon separates the name from the page in the callback data
+        if name.contains(':') {
+            return Err(Error::from(ErrorKind::InvalidPaginatorName));
+        }
+
+        let prefix = format!("{}:", name);
+        let stream = bot.callback_queries_with(move |_: &RcBot, item: &Filterable| {
+            item.callback_query()
+                .and_then(|query| query.data.as_ref())
+                .map(|data| data.starts_with(&prefix))
+                .unwrap_or(false)
+        });
+
+        let paginator = Paginator {
+            inner: Rc::new(PaginatorInner {
+                bot: bot.clone(),
+                name: name.into(),
+                fetch: Box::new(
+                    move |bot: &RcBot,
+                          key: &str,
+                          index: usize|
+                          -> Box<Future<Item = Page, Error = Error>> {
+                        Box::new(fetch(bot, key, index).into_future())
+                    },
+                ),
+                handle: stream.handle(),
+            }),
+        };
+
+        let navigation = paginator.clone();
+        bot.register_with(stream, move |(bot, query)| navigation.navigate(bot, query));
+
+        Ok(paginator)
+    }
+
+    /// Returns the handle of the button handler, which can be unregistered with it
+    pub fn handle(&self) -> Handle {
+        self.inner.handle.clone()
+    }
+
+    /// Sends the first page of the list with the key to the chat
+    pub fn send(
+        &self,
+        chat_id: Integer,
+        key: &str,
+    ) -> Box<Future<Item = (RcBot, Message), Error = Error>> {
+        let bot = self.inner.bot.clone();
+
+        Box::new(self.page(0, key).and_then(move |(page, keyboard)| {
+            let mut msg = bot.message(chat_id, page.text);
+            if let Some(keyboard) = keyboard {
+                msg = msg.reply_markup(keyboard);
+            }
+
+            msg.send()
+        }))
+    }
+
+    /// Fetches a page together with its keyboard
+    fn page(
+        &self,
+        index: usize,
+        key: &str,
+    ) -> Box<Future<Item = (Page, Option<InlineKeyboardMarkup>), Error = Error>> {
+        let paginator = self.clone();
+        let key = key.to_string();
+
+        Box::new(
+            (self.inner.fetch)(&self.inner.bot, &key, index).and_then(move |page| {
+                let keyboard = paginator.keyboard(index, page.count, &key)?;
+
+                Ok((page, keyboard))
+            }),
+        )
+    }
+
+    /// Builds the buttons to the previous and the next page around a page counter, lists with a
+    /// single page have no keyboard
+    fn keyboard(
+        &self,
+        index: usize,
+        count: usize,
+        key: &str,
+    ) -> Result<Option<InlineKeyboardMarkup>, Error> {
+        if count <= 1 {
+            return Ok(None);
+        }
+
+        let mut row = Vec::new();
+        if index > 0 {
+            row.push(self.button("«", index - 1, key)?);
+        }
+
+        // the counter points to the current page, pressing it only refreshes the page
+        row.push(self.button(&format!("{}/{}", index + 1, count), index, key)?);
+
+        if index + 1 < count {
+            row.push(self.button("»", index + 1, key)?);
+        }
+
+        Ok(Some(InlineKeyboardMarkup::new(vec![row])))
+    }
+
+    fn button(&self, text: &str, index: usize, key: &str) -> Result<InlineKeyboardButton, Error> {
+        let data = format!("{}:{}:{}", self.inner.name, index, key);
+        if data.len() > 64 {
+            return Err(Error::from(ErrorKind::CallbackDataTooLong));
+        }
+
+        Ok(InlineKeyboardButton::new(text.into()).callback_data(data))
+    }
+
+    /// Reads the page and the key from the callback data of a button
+    fn parse(&self, data: &str) -> Option<(usize, String)> {
+        let mut parts = data.splitn(3, ':');
+        if parts.next()? != self.inner.name {
+            return None;
+        }
+
+        let index = parts.next()?.parse().ok()?;
+
+        Some((index, parts.next().unwrap_or("").into()))
+    }
+
+    /// Replaces the message of the button with the requested page and answers the query
+    fn navigate(&self, bot: RcBot, query: CallbackQuery) -> Box<Future<Item = (), Error = Error>> {
+        // the query is answered right away, otherwise the client shows a spinner until the page
+        // was fetched and edited
+        bot.inner.handle.spawn(
+            bot.answer_callback_query(query.id.clone())
+                .send()
+                .map(|_| ())
+                .map_err(|e| error!("Couldn't answer the callback query: {}", e)),
+        );
+
+        let parsed = query.data.as_ref().and_then(|data| self.parse(data));
+
+        let (index, key, msg) = match (parsed, query.message) {
+            (Some((index, key)), Some(msg)) => (index, key, msg),
+            // the message is too old or was sent in inline mode
+            _ => return Box::new(future::ok(())),
+        };
+
+        let edit = self.page(index, &key)
+            .and_then(move |(page, keyboard)| {
+                let mut edit = bot.edit_message_text(page.text)
+                    .chat_id(msg.chat.id)
+                    .message_id(msg.message_id);
+                if let Some(keyboard) = keyboard {
+                    edit = edit.reply_markup(keyboard);
+                }
+
+                edit.send().map(|_| ())
+            })
+            .or_else(|e| {
+                // the page didn't change, e.g. after the counter was pressed
+                let not_modified = TelegramError::find(&e)
+                    .map(TelegramError::is_not_modified)
+                    .unwrap_or(false);
+
+                if not_modified {
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            });
+
+        Box::new(edit)
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-chat sequential processing of handler futures
+//!
+//! The futures of handlers registered with `RcBot::register_per_chat` run one after another as
+//! long as they belong to the same chat, even across different handlers. The items of one
+//! handler start in the order in which they arrived. Items of different handlers are queued when
+//! their handler stream yields them, so their order isn't guaranteed, e.g. if one of the
+//! handlers has a guard in front. Items of different chats are handled concurrently, up to the
+//! limit set with `RcBot::max_parallel`.
+//!
+//! ```rust,ignore
+//! let bot = RcBot::new(lp.handle(), &key).max_parallel(8);
+//!
+//! bot.register_per_chat(bot.new_cmd("/slow"), |(bot, msg)| {
+//!     bot.message(msg.chat.id, "done".into()).send()
+//! });
+//! ```
+
+use std::{rc::Rc, cell::{Cell, RefCell}, collections::{HashSet, VecDeque}};
+
+use futures::Future;
+use tokio_core::reactor::Handle;
+
+use bot::RcBot;
+use objects::{CallbackQuery, Chat, Integer, Message, Update};
+use filter::Filterable;
+use error::ErrorOrigin;
+
+type Job = Box<Future<Item = (), Error = ()>>;
+
+/// An item of a handler stream which belongs to a chat
+pub trait ChatItem {
+    /// The chat of the item, items without a chat are not ordered
+    fn chat_id(&self) -> Option<Integer>;
+
+    /// The chat of the item with its kind, if the item carries it
+    fn chat(&self) -> Option<&Chat> {
+        None
+    }
+
+    /// Describes the item for the error hook if its handler fails
+    fn origin(&self) -> ErrorOrigin {
+        ErrorOrigin {
+            chat_id: self.chat_id(),
+            ..ErrorOrigin::default()
+        }
+    }
+}
+
+impl ChatItem for Message {
+    fn chat_id(&self) -> Option<Integer> {
+        Some(self.chat.id)
+    }
+
+    fn chat(&self) -> Option<&Chat> {
+        Some(&self.chat)
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        ErrorOrigin {
+            chat_id: Some(self.chat.id),
+            user_id: self.from.as_ref().map(|user| user.id),
+            message_id: Some(self.message_id),
+            callback_query_id: None,
+        }
+    }
+}
+
+impl ChatItem for CallbackQuery {
+    fn chat_id(&self) -> Option<Integer> {
+        self.message.as_ref().map(|msg| msg.chat.id)
+    }
+
+    fn chat(&self) -> Option<&Chat> {
+        self.message.as_ref().map(|msg| &msg.chat)
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        ErrorOrigin {
+            chat_id: self.chat_id(),
+            user_id: Some(self.from.id),
+            message_id: self.message.as_ref().map(|msg| msg.message_id),
+            callback_query_id: Some(self.id.clone()),
+        }
+    }
+}
+
+impl ChatItem for Update {
+    fn chat_id(&self) -> Option<Integer> {
+        self.message().map(|msg| msg.chat.id)
+    }
+
+    fn chat(&self) -> Option<&Chat> {
+        self.message().map(|msg| &msg.chat)
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        ErrorOrigin {
+            chat_id: self.chat_id(),
+            user_id: self.sender().map(|user| user.id),
+            message_id: self.message().map(|msg| msg.message_id),
+            callback_query_id: self.callback_query.as_ref().map(|query| query.id.clone()),
+        }
+    }
+}
+
+impl<T: ChatItem> ChatItem for (RcBot, T) {
+    fn chat_id(&self) -> Option<Integer> {
+        self.1.chat_id()
+    }
+
+    fn chat(&self) -> Option<&Chat> {
+        self.1.chat()
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        self.1.origin()
+    }
+}
+
+impl<A> ChatItem for (RcBot, Message, A) {
+    fn chat_id(&self) -> Option<Integer> {
+        self.1.chat_id()
+    }
+
+    fn chat(&self) -> Option<&Chat> {
+        self.1.chat()
+    }
+
+    fn origin(&self) -> ErrorOrigin {
+        self.1.origin()
+    }
+}
+
+struct QueueInner {
+    handle: Handle,
+    limit: Cell<Option<usize>>,
+    running: Cell<usize>,
+    busy: RefCell<HashSet<Integer>>,
+    pending: RefCell<VecDeque<(Option<Integer>, Job)>>,
+}
+
+/// Runs at most one job per chat at a time and limits the number of jobs running overall.
+/// Cloning the queue returns a handle to the same queue.
+#[derive(Clone)]
+pub struct ChatQueue {
+    inner: Rc<QueueInner>,
+}
+
+impl ChatQueue {
+    pub fn new(handle: Handle) -> ChatQueue {
+        ChatQueue {
+            inner: Rc::new(QueueInner {
+                handle,
+                limit: Cell::new(None),
+                running: Cell::new(0),
+                busy: RefCell::new(HashSet::new()),
+                pending: RefCell::new(VecDeque::new()),
+            }),
+        }
+    }
+
+    /// Sets the maximal number of jobs which run at the same time, None removes the limit. A
+    /// limit of zero is raised to one, since no job could ever start otherwise.
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.inner.limit.set(limit.map(|limit| limit.max(1)));
+        self.pump();
+    }
+
+    /// The number of jobs which are currently running
+    pub fn running(&self) -> usize {
+        self.inner.running.get()
+    }
+
+    /// The number of jobs which wait for their chat or a free slot
+    pub fn pending(&self) -> usize {
+        self.inner.pending.borrow().len()
+    }
+
+    /// Adds a job to the queue, it is started once no other job of the chat is running
+    pub fn push<F>(&self, chat_id: Option<Integer>, job: F)
+    where
+        F: Future<Item = (), Error = ()> + 'static,
+    {
+        self.inner
+            .pending
+            .borrow_mut()
+            .push_back((chat_id, Box::new(job)));
+
+        self.pump();
+    }
+
+    /// Starts waiting jobs in the order of their arrival until the limit is reached
+    fn pump(&self) {
+        loop {
+            if let Some(limit) = self.inner.limit.get() {
+                if self.inner.running.get() >= limit {
+                    return;
+                }
+            }
+
+            let next = {
+                let busy = self.inner.busy.borrow();
+                let mut pending = self.inner.pending.borrow_mut();
+
+                // the first job of a chat which isn't busy is always its oldest one
+                let idx = pending.iter().position(|&(chat_id, _)| match chat_id {
+                    Some(id) => !busy.contains(&id),
+                    None => true,
+                });
+
+                idx.and_then(|idx| pending.remove(idx))
+            };
+
+            let (chat_id, job) = match next {
+                Some(next) => next,
+                None => return,
+            };
+
+            if let Some(id) = chat_id {
+                self.inner.busy.borrow_mut().insert(id);
+            }
+            self.inner.running.set(self.inner.running.get() + 1);
+
+            let queue = self.clone();
+            self.inner.handle.spawn(job.then(move |_| -> Result<(), ()> {
+                queue.finish(chat_id);
+
+                Ok(())
+            }));
+        }
+    }
+
+    fn finish(&self, chat_id: Option<Integer>) {
+        if let Some(id) = chat_id {
+            self.inner.busy.borrow_mut().remove(&id);
+        }
+        self.inner.running.set(self.inner.running.get() - 1);
+
+        self.pump();
+    }
+}
@@ -0,0 +1,81 @@
+//! Shortcuts to answer a message
+//!
+//! The `Reply` trait builds the usual functions for a received message, the chat and the id of
+//! the message are already filled in. The returned wrappers are sent like every other function:
+//!
+//! ```rust,ignore
+//! use telebot::reply::Reply;
+//!
+//! let handle = bot.new_cmd("/report").and_then(|(bot, msg)| {
+//!     msg.react_typing(&bot).send()
+//!         .and_then(move |(bot, _)| {
+//!             msg.reply_document(&bot).file("report.pdf").caption("The report").send()
+//!         })
+//! });
+//! ```
+
+use bot::RcBot;
+use functions::{Action, FunctionDeleteMessage, FunctionEditMessageText, FunctionForwardMessage,
+                FunctionMessage, FunctionSendAction, FunctionSendDocument, FunctionSendPhoto,
+                WrapperDeleteMessage, WrapperEditMessageText, WrapperForwardMessage,
+                WrapperMessage, WrapperSendAction, WrapperSendDocument, WrapperSendPhoto};
+use objects::{Integer, Message};
+
+/// Functions which refer to a received message
+pub trait Reply {
+    /// Answers the message with a text, which is sent as reply to it
+    fn reply_text<S: Into<String>>(&self, bot: &RcBot, text: S) -> WrapperMessage;
+
+    /// Answers the message with a photo, which has to be set with `file`, `file_id` or `url`
+    fn reply_photo(&self, bot: &RcBot) -> WrapperSendPhoto;
+
+    /// Answers the message with a document, which has to be set with `file`, `file_id` or `url`
+    fn reply_document(&self, bot: &RcBot) -> WrapperSendDocument;
+
+    /// Replaces the text of the message, which works only for messages sent by the bot
+    fn edit<S: Into<String>>(&self, bot: &RcBot, text: S) -> WrapperEditMessageText;
+
+    /// Deletes the message
+    fn delete(&self, bot: &RcBot) -> WrapperDeleteMessage;
+
+    /// Forwards the message to another chat
+    fn forward_to(&self, bot: &RcBot, chat_id: Integer) -> WrapperForwardMessage;
+
+    /// Shows that the bot is typing in the chat of the message
+    fn react_typing(&self, bot: &RcBot) -> WrapperSendAction;
+}
+
+impl Reply for Message {
+    fn reply_text<S: Into<String>>(&self, bot: &RcBot, text: S) -> WrapperMessage {
+        bot.message(self.chat.id, text.into())
+            .reply_to_message_id(self.message_id)
+    }
+
+    fn reply_photo(&self, bot: &RcBot) -> WrapperSendPhoto {
+        bot.photo(self.chat.id)
+            .reply_to_message_id(self.message_id)
+    }
+
+    fn reply_document(&self, bot: &RcBot) -> WrapperSendDocument {
+        bot.document(self.chat.id)
+            .reply_to_message_id(self.message_id)
+    }
+
+    fn edit<S: Into<String>>(&self, bot: &RcBot, text: S) -> WrapperEditMessageText {
+        bot.edit_message_text(text.into())
+            .chat_id(self.chat.id)
+            .message_id(self.message_id)
+    }
+
+    fn delete(&self, bot: &RcBot) -> WrapperDeleteMessage {
+        bot.delete_message(self.chat.id, self.message_id)
+    }
+
+    fn forward_to(&self, bot: &RcBot, chat_id: Integer) -> WrapperForwardMessage {
+        bot.forward_message(chat_id, self.chat.id, self.message_id)
+    }
+
+    fn react_typing(&self, bot: &RcBot) -> WrapperSendAction {
+        bot.chat_action(self.chat.id, Action::Typing.into())
+    }
+}
@@ -0,0 +1,105 @@
+//! Runs several bots on one reactor
+//!
+//! Every bot keeps its own handlers. Handlers which all bots share are registered for each bot
+//! with `MultiBot::setup`, the `RcBot` passed to a handler is always the bot which received the
+//! update:
+//!
+//! ```rust,ignore
+//! let mut lp = Core::new().unwrap();
+//!
+//! let bots = MultiBot::new()
+//!     .bot(RcBot::new(lp.handle(), &key_a))
+//!     .bot(RcBot::new(lp.handle(), &key_b));
+//!
+//! // shared by all bots
+//! bots.setup(|bot| {
+//!     let handle = bot.new_cmd("/ping").and_then(|(bot, msg)| {
+//!         bot.message(msg.chat.id, "pong".into()).send()
+//!     });
+//!     bot.register(handle);
+//! });
+//!
+//! // only for the first bot
+//! let handle = bots.bots()[0].new_cmd("/admin")...;
+//!
+//! bots.run(&mut lp).unwrap();
+//! ```
+
+use failure::{Error, ResultExt};
+use futures::{stream, Future, Stream};
+use tokio_core::reactor::Core;
+
+use bot::RcBot;
+use error::ErrorKind;
+use objects::Update;
+
+/// A set of bots which poll for updates on the same reactor
+#[derive(Clone, Default)]
+pub struct MultiBot {
+    bots: Vec<RcBot>,
+}
+
+impl MultiBot {
+    pub fn new() -> MultiBot {
+        MultiBot::default()
+    }
+
+    /// Adds a bot, it has to use a handle of the reactor which runs the bots
+    pub fn bot(mut self, bot: RcBot) -> MultiBot {
+        self.bots.push(bot);
+
+        self
+    }
+
+    /// Returns all bots in the order in which they were added
+    pub fn bots(&self) -> &[RcBot] {
+        &self.bots
+    }
+
+    /// Calls the function for every bot, e.g. to register handlers which all bots share
+    pub fn setup<F>(&self, f: F)
+    where
+        F: Fn(&RcBot),
+    {
+        for bot in &self.bots {
+            f(bot);
+        }
+    }
+
+    /// Returns the updates of all bots which weren't taken by a handler. Every update comes
+    /// together with the bot which received it.
+    ///
+    /// The bots are isolated from each other, an error while polling or dispatching the updates
+    /// of one bot is logged and the bot continues with its next poll. The stream therefore never
+    /// fails.
+    pub fn get_stream<'a>(&'a self) -> Box<Stream<Item = (RcBot, Update), Error = Error> + 'a> {
+        self.bots.iter().enumerate().fold(
+            Box::new(stream::empty()),
+            |merged: Box<Stream<Item = _, Error = _> + 'a>, (idx, bot)| {
+                let updates = bot.get_stream()
+                    .then(move |result| match result {
+                        Ok(update) => Ok(Some(update)),
+                        Err(e) => {
+                            error!("The bot #{} failed to receive updates: {}", idx, e);
+
+                            Ok(None)
+                        }
+                    })
+                    .filter_map(|update| update);
+
+                Box::new(merged.select(updates))
+            },
+        )
+    }
+
+    /// Starts all bots and runs the reactor as long as the bots poll for updates
+    pub fn run(&self, core: &mut Core) -> Result<(), Error> {
+        for bot in &self.bots {
+            bot.start();
+        }
+
+        core.run(self.get_stream().for_each(|_| Ok(())).into_future())
+            .context(ErrorKind::Tokio)
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,186 @@
+//! Calls of the Telegram API which are sent at a later time and survive restarts
+//!
+//! A scheduled call stores the method, the chat and the parameters of the call in a `CallStore`.
+//! The calls of the store are loaded again when the store is set with `RcBot::call_store`, calls
+//! which became due while the bot was offline are sent right away. A call stays in the store
+//! until Telegram accepted it. If the request fails because of the network or a server error of
+//! Telegram, the call is sent again later:
+//!
+//! ```rust,ignore
+//! let bot = RcBot::new(lp.handle(), &key)
+//!     .call_store(JsonFileCallStore::open("scheduled.json")?);
+//!
+//! let due = Utc::now() + chrono::Duration::hours(1);
+//! let id = bot.schedule_message(msg.chat.id, "Time for a break!", due)?;
+//!
+//! // list and cancel the calls of a chat
+//! for call in bot.scheduled_calls(msg.chat.id)? {
+//!     bot.cancel_scheduled_call(&call.id)?;
+//! }
+//! ```
+
+use std::{fs, path::PathBuf, cell::RefCell, collections::HashMap};
+
+use chrono::{DateTime, TimeZone, Utc};
+use failure::{Error, ResultExt};
+use serde_json::{self, Value};
+use uuid::Uuid;
+
+use error::ErrorKind;
+use objects::Integer;
+use session::write_atomic;
+
+/// A call of the Telegram API which is sent at the due time
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScheduledCall {
+    pub id: String,
+    pub chat_id: Integer,
+    /// The name of the method, e.g. "sendMessage"
+    pub method: String,
+    /// The parameters of the call, the chat id is set when the call is sent
+    pub params: Value,
+    /// The due time as unix timestamp in seconds
+    pub due: i64,
+}
+
+impl ScheduledCall {
+    pub fn new(
+        chat_id: Integer,
+        method: &str,
+        params: Value,
+        due: DateTime<Utc>,
+    ) -> ScheduledCall {
+        ScheduledCall {
+            id: Uuid::new_v4().to_string(),
+            chat_id,
+            method: method.into(),
+            params,
+            due: due.timestamp(),
+        }
+    }
+
+    /// The due time of the call, or None if the timestamp is out of range, e.g. in an edited
+    /// store
+    pub fn due(&self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_opt(self.due, 0).single()
+    }
+
+    /// The JSON body of the request
+    pub fn body(&self) -> Result<String, Error> {
+        let mut params = match self.params {
+            Value::Object(ref params) => params.clone(),
+            Value::Null => serde_json::Map::new(),
+            _ => return Err(Error::from(ErrorKind::JsonNotMap)),
+        };
+
+        params.insert("chat_id".into(), Value::from(self.chat_id));
+
+        Ok(serde_json::to_string(&params).context(ErrorKind::JsonSerialize)?)
+    }
+}
+
+/// A durable backend for scheduled calls
+pub trait CallStore {
+    /// Returns all stored calls
+    fn all(&self) -> Result<Vec<ScheduledCall>, Error>;
+
+    /// Returns the call with the id
+    fn get(&self, id: &str) -> Result<Option<ScheduledCall>, Error> {
+        Ok(self.all()?.into_iter().find(|call| call.id == id))
+    }
+
+    /// Stores a call, a call with the same id is replaced
+    fn insert(&self, call: ScheduledCall) -> Result<(), Error>;
+
+    /// Removes a call and returns it
+    fn remove(&self, id: &str) -> Result<Option<ScheduledCall>, Error>;
+}
+
+/// A call store which keeps the calls in memory, the calls are lost on restart
+#[derive(Default)]
+pub struct MemoryCallStore {
+    calls: RefCell<HashMap<String, ScheduledCall>>,
+}
+
+impl MemoryCallStore {
+    pub fn new() -> MemoryCallStore {
+        MemoryCallStore::default()
+    }
+}
+
+impl CallStore for MemoryCallStore {
+    fn all(&self) -> Result<Vec<ScheduledCall>, Error> {
+        Ok(self.calls.borrow().values().cloned().collect())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<ScheduledCall>, Error> {
+        Ok(self.calls.borrow().get(id).cloned())
+    }
+
+    fn insert(&self, call: ScheduledCall) -> Result<(), Error> {
+        self.calls.borrow_mut().insert(call.id.clone(), call);
+
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> Result<Option<ScheduledCall>, Error> {
+        Ok(self.calls.borrow_mut().remove(id))
+    }
+}
+
+/// A call store which writes the calls to a JSON file after every change
+pub struct JsonFileCallStore {
+    path: PathBuf,
+    memory: MemoryCallStore,
+}
+
+impl JsonFileCallStore {
+    /// Opens the store at the given path, the file is created on the first change
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<JsonFileCallStore, Error> {
+        let path = path.into();
+        let memory = MemoryCallStore::new();
+
+        if path.is_file() {
+            let content = fs::read_to_string(&path).context(ErrorKind::IO)?;
+            let calls: Vec<ScheduledCall> =
+                serde_json::from_str(&content).context(ErrorKind::JsonParse)?;
+
+            for call in calls {
+                memory.insert(call)?;
+            }
+        }
+
+        Ok(JsonFileCallStore { path, memory })
+    }
+
+    fn persist(&self) -> Result<(), Error> {
+        let calls = self.memory.all()?;
+        let content = serde_json::to_string(&calls).context(ErrorKind::JsonSerialize)?;
+
+        write_atomic(&self.path, &content)
+    }
+}
+
+impl CallStore for JsonFileCallStore {
+    fn all(&self) -> Result<Vec<ScheduledCall>, Error> {
+        self.memory.all()
+    }
+
+    fn get(&self, id: &str) -> Result<Option<ScheduledCall>, Error> {
+        self.memory.get(id)
+    }
+
+    fn insert(&self, call: ScheduledCall) -> Result<(), Error> {
+        self.memory.insert(call)?;
+        self.persist()
+    }
+
+    fn remove(&self, id: &str) -> Result<Option<ScheduledCall>, Error> {
+        let call = self.memory.remove(id)?;
+        if call.is_some() {
+            self.persist()?;
+        }
+
+        Ok(call)
+    }
+}
@@ -0,0 +1,630 @@
+//! Jobs which run after a delay, in fixed intervals or on a cron schedule
+//!
+//! Jobs are added with `RcBot::schedule` or `RcBot::schedule_for_chat` and receive the bot when
+//! they run. The scheduler checks for due jobs once a second on the reactor of the bot:
+//!
+//! ```rust,ignore
+//! let digest = bot.schedule(Schedule::cron("0 8 * * 1-5")?, |bot| {
+//!     bot.message(CHANNEL_ID, "Good morning!".into()).send().map(|_| ())
+//! })?;
+//!
+//! // later
+//! bot.cancel_job(digest);
+//! ```
+//!
+//! The time is taken from a `Clock`. Schedules can be tested without waiting by setting a
+//! `ManualClock` with `RcBot::clock`, moving it forward and calling `RcBot::run_due_jobs`.
+//! Cron expressions are evaluated in UTC.
+
+use std::{fmt, rc::Rc, str::FromStr, time::Duration, cell::{Cell, RefCell},
+          collections::HashMap};
+
+use chrono::{self, DateTime, Datelike, NaiveDate, Timelike, Utc};
+use failure::Error;
+use futures::{Future, IntoFuture};
+
+use bot::RcBot;
+use error::{ErrorKind, ErrorOrigin};
+use objects::Integer;
+
+/// The id of a scheduled job
+pub type JobId = usize;
+
+type JobHandler = Fn(RcBot, Option<Integer>) -> Box<Future<Item = (), Error = Error>>;
+
+/// The source of the current time
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The time of the system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock which only moves when it is told to
+pub struct ManualClock {
+    now: Cell<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> ManualClock {
+        ManualClock {
+            now: Cell::new(now),
+        }
+    }
+
+    /// Sets the current time
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.set(now);
+    }
+
+    /// Moves the clock forward
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(add(self.now.get(), duration));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.get()
+    }
+}
+
+/// Adds a duration to a point in time, saturating at the largest representable date
+fn add(time: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| time.checked_add_signed(duration))
+        .unwrap_or_else(|| chrono::MAX_DATE.and_hms(0, 0, 0))
+}
+
+/// When a job runs
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    /// Once at the given time
+    At(DateTime<Utc>),
+    /// Once after the delay
+    After(Duration),
+    /// Repeatedly with the interval, the first run is after one interval
+    Every(Duration),
+    /// Repeatedly at the times matching the cron expression
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    /// Parses a cron expression, see `CronSchedule`
+    pub fn cron(expr: &str) -> Result<Schedule, Error> {
+        Ok(Schedule::Cron(expr.parse()?))
+    }
+
+    /// The first run of a job added at the given time
+    fn first(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match *self {
+            Schedule::At(time) => Some(time),
+            Schedule::After(delay) | Schedule::Every(delay) => Some(add(now, delay)),
+            Schedule::Cron(ref cron) => cron.next_after(now),
+        }
+    }
+
+    /// The run following the one which was due, runs missed in the meantime are skipped
+    fn next(&self, due: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match *self {
+            Schedule::At(_) | Schedule::After(_) => None,
+            Schedule::Every(interval) if interval > Duration::from_secs(0) => {
+                let mut next = add(due, interval);
+                while next <= now {
+                    next = add(next, interval);
+                }
+
+                Some(next)
+            }
+            Schedule::Every(_) => Some(now),
+            Schedule::Cron(ref cron) => cron.next_after(now),
+        }
+    }
+}
+
+/// A cron expression with the five fields minute, hour, day of month, month and day of week.
+/// Each field is `*`, a number, a range `a-b`, a step `*/n` or `a-b/n`, or a list of these
+/// separated by commas. Sunday is 0 or 7. As in cron, a day matches if either the day of month or
+/// the day of week matches when both are restricted.
+#[derive(Clone)]
+pub struct CronSchedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl fmt::Debug for CronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CronSchedule")
+    }
+}
+
+/// Parses one field of a cron expression into the set of matching values
+fn parse_field(field: &str, min: usize, max: usize) -> Result<Vec<bool>, Error> {
+    let mut values = vec![false; max + 1];
+
+    for part in field.split(',') {
+        let mut split = part.splitn(2, '/');
+        let range = split.next().unwrap_or("");
+        let step = match split.next() {
+            Some(step) => step.parse::<usize>().map_err(|_| ErrorKind::Cron)?,
+            None => 1,
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(idx) = range.find('-') {
+            let start = range[..idx].parse::<usize>().map_err(|_| ErrorKind::Cron)?;
+            let end = range[idx + 1..].parse::<usize>().map_err(|_| ErrorKind::Cron)?;
+
+            (start, end)
+        } else {
+            let start = range.parse::<usize>().map_err(|_| ErrorKind::Cron)?;
+
+            // "5/15" means every 15 starting at 5
+            if part.contains('/') {
+                (start, max)
+            } else {
+                (start, start)
+            }
+        };
+
+        if step == 0 || start < min || start > end || end > max {
+            return Err(Error::from(ErrorKind::Cron));
+        }
+
+        for value in (start..end + 1).step_by(step) {
+            values[value] = true;
+        }
+    }
+
+    Ok(values)
+}
+
+impl FromStr for CronSchedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<CronSchedule, Error> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(Error::from(ErrorKind::Cron));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is another name for sunday
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.days[date.day() as usize];
+        let weekday = self.weekdays[date.weekday().num_days_from_sunday() as usize];
+
+        self.months[date.month() as usize] && match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    /// The first matching minute after the given time, None if there is none in the next years
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = (after + chrono::Duration::minutes(1)).naive_utc();
+
+        // five years cover every valid combination, e.g. the 29th of february on a monday
+        for offset in 0..(5 * 366) {
+            let date = start.date() + chrono::Duration::days(offset);
+            if !self.day_matches(date) {
+                continue;
+            }
+
+            let (first_hour, first_minute) = if offset == 0 {
+                (start.hour() as usize, start.minute() as usize)
+            } else {
+                (0, 0)
+            };
+
+            for hour in (first_hour..24).filter(|&hour| self.hours[hour]) {
+                let first_minute = if offset == 0 && hour == first_hour {
+                    first_minute
+                } else {
+                    0
+                };
+
+                if let Some(minute) = (first_minute..60).find(|&minute| self.minutes[minute]) {
+                    let time = date.and_hms(hour as u32, minute as u32, 0);
+
+                    return Some(DateTime::from_utc(time, Utc));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+struct Job {
+    schedule: Schedule,
+    next: DateTime<Utc>,
+    chat_id: Option<Integer>,
+    handler: Rc<JobHandler>,
+}
+
+struct SchedulerInner {
+    clock: RefCell<Rc<Clock>>,
+    jobs: RefCell<HashMap<JobId, Job>>,
+    next_id: Cell<JobId>,
+    started: Cell<bool>,
+}
+
+/// The jobs of a bot, cloning the scheduler returns a handle to the same jobs
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Rc<SchedulerInner>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            inner: Rc::new(SchedulerInner {
+                clock: RefCell::new(Rc::new(SystemClock)),
+                jobs: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                started: Cell::new(false),
+            }),
+        }
+    }
+
+    /// Replaces the clock, e.g. with a `ManualClock` in tests
+    pub fn set_clock(&self, clock: Rc<Clock>) {
+        *self.inner.clock.borrow_mut() = clock;
+    }
+
+    /// The current time of the clock
+    pub fn now(&self) -> DateTime<Utc> {
+        self.inner.clock.borrow().now()
+    }
+
+    /// Marks the scheduler as driven by a timer, returns false if it already was
+    pub fn start(&self) -> bool {
+        !self.inner.started.replace(true)
+    }
+
+    /// Adds a job, which is passed the chat id when it runs. Fails if the schedule never runs.
+    pub fn add<F, R>(
+        &self,
+        schedule: Schedule,
+        chat_id: Option<Integer>,
+        handler: F,
+    ) -> Result<JobId, Error>
+    where
+        F: Fn(RcBot, Option<Integer>) -> R + 'static,
+        R: IntoFuture<Item = (), Error = Error> + 'static,
+    {
+        let next = schedule.first(self.now()).ok_or(ErrorKind::Cron)?;
+
+        let id = self.inner.next_id.get();
+        self.inner.next_id.set(id + 1);
+
+        let handler: Rc<JobHandler> =
+            Rc::new(move |bot, chat_id| Box::new(handler(bot, chat_id).into_future()));
+
+        self.inner.jobs.borrow_mut().insert(
+            id,
+            Job {
+                schedule,
+                next,
+                chat_id,
+                handler,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Removes a job, returns false if it doesn't exist or already finished
+    pub fn cancel(&self, id: JobId) -> bool {
+        self.inner.jobs.borrow_mut().remove(&id).is_some()
+    }
+
+    /// The time of the next run of a job
+    pub fn next_run(&self, id: JobId) -> Option<DateTime<Utc>> {
+        self.inner.jobs.borrow().get(&id).map(|job| job.next)
+    }
+
+    /// The ids of all jobs bound to the chat
+    pub fn jobs_of_chat(&self, chat_id: Integer) -> Vec<JobId> {
+        self.inner
+            .jobs
+            .borrow()
+            .iter()
+            .filter(|&(_, job)| job.chat_id == Some(chat_id))
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Binds the jobs of a group to the supergroup it was upgraded to
+    pub fn migrate_chat(&self, from: Integer, to: Integer) {
+        for job in self.inner.jobs.borrow_mut().values_mut() {
+            if job.chat_id == Some(from) {
+                job.chat_id = Some(to);
+            }
+        }
+    }
+
+    /// Starts every job which is due, in the order of their due time
+    pub fn tick(&self, bot: &RcBot) {
+        let now = self.now();
+
+        let mut due = {
+            let mut jobs = self.inner.jobs.borrow_mut();
+
+            let ids = jobs.iter()
+                .filter(|&(_, job)| job.next <= now)
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+
+            let mut due = Vec::new();
+            for id in ids {
+                let next = {
+                    let job = &jobs[&id];
+                    due.push((job.next, id, job.chat_id, job.handler.clone()));
+
+                    job.schedule.next(job.next, now)
+                };
+
+                match next {
+                    Some(next) => {
+                        if let Some(job) = jobs.get_mut(&id) {
+                            job.next = next;
+                        }
+                    }
+                    None => {
+                        jobs.remove(&id);
+                    }
+                }
+            }
+
+            due
+        };
+
+        due.sort_by_key(|&(time, id, _, _)| (time, id));
+
+        for (_, id, chat_id, handler) in due {
+            debug!("Run the scheduled job {}", id);
+
+            let bot2 = bot.clone();
+            let origin = ErrorOrigin {
+                chat_id,
+                ..ErrorOrigin::default()
+            };
+
+            bot.inner.handle.spawn(
+                handler(bot.clone(), chat_id).or_else(move |e| -> Result<(), ()> {
+                    bot2.report_error(e, origin);
+
+                    Ok(())
+                }),
+            );
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{rc::Rc, cell::Cell, time::Duration};
+
+    use chrono::TimeZone;
+    use tokio_core::reactor::Core;
+
+    fn time(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(year, month, day).and_hms(hour, minute, 0)
+    }
+
+    fn next(expr: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        expr.parse::<CronSchedule>().unwrap().next_after(after)
+    }
+
+    #[test]
+    fn parses_valid_expressions() {
+        for expr in &[
+            "* * * * *",
+            "0 8 * * 1-5",
+            "*/15 0,12 1 1-6/2 7",
+            "5/10 * * * *",
+            "0 0 31 2 *",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_ok(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in &[
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "30-10 * * * *",
+            "a * * * *",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn next_minute_is_strictly_after() {
+        let after = Utc.ymd(2018, 6, 4).and_hms(10, 0, 30);
+
+        assert_eq!(next("* * * * *", after), Some(time(2018, 6, 4, 10, 1)));
+        assert_eq!(
+            next("* * * * *", time(2018, 6, 4, 10, 1)),
+            Some(time(2018, 6, 4, 10, 2))
+        );
+    }
+
+    #[test]
+    fn steps_and_ranges() {
+        assert_eq!(
+            next("*/15 * * * *", time(2018, 6, 4, 10, 1)),
+            Some(time(2018, 6, 4, 10, 15))
+        );
+        assert_eq!(
+            next("10-30/10 * * * *", time(2018, 6, 4, 10, 25)),
+            Some(time(2018, 6, 4, 10, 30))
+        );
+        assert_eq!(
+            next("10-30/10 * * * *", time(2018, 6, 4, 10, 30)),
+            Some(time(2018, 6, 4, 11, 10))
+        );
+        assert_eq!(
+            next("0 23 * * *", time(2018, 6, 4, 23, 0)),
+            Some(time(2018, 6, 5, 23, 0))
+        );
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        // the 4th of june 2018 is a monday
+        let monday = time(2018, 6, 4, 0, 0);
+
+        assert_eq!(next("0 12 * * 7", monday), Some(time(2018, 6, 10, 12, 0)));
+        assert_eq!(next("0 12 * * 0", monday), Some(time(2018, 6, 10, 12, 0)));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // the 13th or every friday
+        assert_eq!(
+            next("0 0 13 * 5", time(2018, 6, 4, 0, 0)),
+            Some(time(2018, 6, 8, 0, 0))
+        );
+        assert_eq!(
+            next("0 0 13 * 5", time(2018, 6, 9, 0, 0)),
+            Some(time(2018, 6, 13, 0, 0))
+        );
+
+        // only the day of month is restricted
+        assert_eq!(
+            next("0 0 13 * *", time(2018, 6, 4, 0, 0)),
+            Some(time(2018, 6, 13, 0, 0))
+        );
+    }
+
+    #[test]
+    fn rare_and_impossible_dates() {
+        assert_eq!(
+            next("0 0 29 2 *", time(2018, 3, 1, 0, 0)),
+            Some(time(2020, 2, 29, 0, 0))
+        );
+        assert_eq!(next("0 0 31 2 *", time(2018, 3, 1, 0, 0)), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", time(2018, 3, 1, 0, 0)), None);
+
+        let scheduler = Scheduler::new();
+        let added = scheduler.add(Schedule::cron("0 0 31 2 *").unwrap(), None, |_, _| {
+            Ok::<(), Error>(())
+        });
+        assert!(added.is_err());
+    }
+
+    #[test]
+    fn intervals_skip_missed_runs() {
+        let every = Schedule::Every(Duration::from_secs(60));
+        let due = time(2018, 6, 4, 10, 0);
+        let now = Utc.ymd(2018, 6, 4).and_hms(10, 5, 30);
+
+        assert_eq!(every.next(due, now), Some(time(2018, 6, 4, 10, 6)));
+        assert_eq!(every.next(due, due), Some(time(2018, 6, 4, 10, 1)));
+        assert_eq!(Schedule::After(Duration::from_secs(60)).next(due, now), None);
+        assert_eq!(
+            Schedule::cron("0 * * * *").unwrap().next(due, now),
+            Some(time(2018, 6, 4, 11, 0))
+        );
+    }
+
+    #[test]
+    fn runs_due_jobs_with_a_manual_clock() {
+        let mut core = Core::new().unwrap();
+        let start = time(2018, 6, 4, 10, 0);
+        let clock = Rc::new(ManualClock::new(start));
+        let bot = RcBot::new(core.handle(), "").clock(clock.clone());
+
+        let once = Rc::new(Cell::new(0));
+        let repeated = Rc::new(Cell::new(0));
+
+        let counter = once.clone();
+        let delayed = bot.schedule(Schedule::After(Duration::from_secs(60)), move |_| {
+            counter.set(counter.get() + 1);
+            Ok(())
+        }).unwrap();
+
+        let counter = repeated.clone();
+        let interval = bot.schedule(Schedule::Every(Duration::from_secs(10)), move |_| {
+            counter.set(counter.get() + 1);
+            Ok(())
+        }).unwrap();
+
+        let mut run = |bot: &RcBot| {
+            bot.run_due_jobs();
+            for _ in 0..3 {
+                core.turn(Some(Duration::from_millis(10)));
+            }
+        };
+
+        run(&bot);
+        assert_eq!((once.get(), repeated.get()), (0, 0));
+
+        // the interval ran three times meanwhile, but missed runs are skipped
+        clock.advance(Duration::from_secs(35));
+        run(&bot);
+        assert_eq!((once.get(), repeated.get()), (0, 1));
+        assert_eq!(
+            bot.inner.scheduler.next_run(interval),
+            Some(Utc.ymd(2018, 6, 4).and_hms(10, 0, 40))
+        );
+
+        clock.advance(Duration::from_secs(25));
+        run(&bot);
+        assert_eq!((once.get(), repeated.get()), (1, 2));
+        assert_eq!(bot.inner.scheduler.next_run(delayed), None);
+    }
+}
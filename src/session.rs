@@ -0,0 +1,255 @@
+//! Per-chat and per-user session data
+//!
+//! A session store keeps named values for each chat or user. The store of a bot is set with
+//! `RcBot::session_store` and reached from every handler with `RcBot::session`:
+//!
+//! ```rust,ignore
+//! let bot = RcBot::new(lp.handle(), &key)
+//!     .session_store(JsonFileStore::open("sessions.json")?);
+//!
+//! let handle = bot.new_cmd("/count").and_then(|(bot, msg)| {
+//!     let key = SessionKey::Chat(msg.chat.id);
+//!     let count = bot.session().get::<u32>(key, "count")?.unwrap_or(0) + 1;
+//!     bot.session().set(key, "count", &count)?;
+//!     ...
+//! });
+//! ```
+
+use std::{fmt, fs, io::Write, path::{Path, PathBuf}, rc::Rc, str::FromStr, cell::RefCell,
+          collections::HashMap};
+
+use failure::{Error, ResultExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{self, Value};
+
+use error::ErrorKind;
+use objects::Integer;
+
+/// The owner of a session, either a chat or a user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SessionKey {
+    Chat(Integer),
+    User(Integer),
+}
+
+impl fmt::Display for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionKey::Chat(id) => write!(f, "chat:{}", id),
+            SessionKey::User(id) => write!(f, "user:{}", id),
+        }
+    }
+}
+
+impl FromStr for SessionKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<SessionKey, Error> {
+        let mut parts = s.splitn(2, ':');
+
+        match (parts.next(), parts.next().and_then(|id| id.parse().ok())) {
+            (Some("chat"), Some(id)) => Ok(SessionKey::Chat(id)),
+            (Some("user"), Some(id)) => Ok(SessionKey::User(id)),
+            _ => Err(Error::from(ErrorKind::JsonParse)),
+        }
+    }
+}
+
+/// A backend which stores the values of each session as JSON
+pub trait SessionStore {
+    /// Returns the value with the given name
+    fn get(&self, key: SessionKey, name: &str) -> Result<Option<Value>, Error>;
+
+    /// Sets the value with the given name
+    fn set(&self, key: SessionKey, name: &str, value: Value) -> Result<(), Error>;
+
+    /// Deletes the value with the given name
+    fn delete(&self, key: SessionKey, name: &str) -> Result<(), Error>;
+
+    /// Moves all values of a session to another key, values which already exist under the new
+    /// key are kept. Used when a group is upgraded to a supergroup with a new chat id.
+    fn rename(&self, from: SessionKey, to: SessionKey) -> Result<(), Error>;
+}
+
+/// A session store which keeps all values in memory, the values are lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: RefCell<HashMap<SessionKey, HashMap<String, Value>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn get(&self, key: SessionKey, name: &str) -> Result<Option<Value>, Error> {
+        Ok(self.sessions
+            .borrow()
+            .get(&key)
+            .and_then(|session| session.get(name))
+            .cloned())
+    }
+
+    fn set(&self, key: SessionKey, name: &str, value: Value) -> Result<(), Error> {
+        self.sessions
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(HashMap::new)
+            .insert(name.into(), value);
+
+        Ok(())
+    }
+
+    fn delete(&self, key: SessionKey, name: &str) -> Result<(), Error> {
+        let mut sessions = self.sessions.borrow_mut();
+
+        let empty = match sessions.get_mut(&key) {
+            Some(session) => {
+                session.remove(name);
+                session.is_empty()
+            }
+            None => false,
+        };
+
+        if empty {
+            sessions.remove(&key);
+        }
+
+        Ok(())
+    }
+
+    fn rename(&self, from: SessionKey, to: SessionKey) -> Result<(), Error> {
+        let mut sessions = self.sessions.borrow_mut();
+
+        if let Some(old) = sessions.remove(&from) {
+            let new = sessions.entry(to).or_insert_with(HashMap::new);
+
+            for (name, value) in old {
+                new.entry(name).or_insert(value);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A session store which keeps the values in memory and writes them to a JSON file after every
+/// change. The file is replaced atomically, so it is never left half written.
+pub struct JsonFileStore {
+    path: PathBuf,
+    memory: MemoryStore,
+}
+
+impl JsonFileStore {
+    /// Opens the store at the given path, the file is created on the first change
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<JsonFileStore, Error> {
+        let path = path.into();
+        let memory = MemoryStore::new();
+
+        if path.is_file() {
+            let content = fs::read_to_string(&path).context(ErrorKind::IO)?;
+            let sessions: HashMap<String, HashMap<String, Value>> =
+                serde_json::from_str(&content).context(ErrorKind::JsonParse)?;
+
+            for (key, session) in sessions {
+                memory
+                    .sessions
+                    .borrow_mut()
+                    .insert(key.parse()?, session);
+            }
+        }
+
+        Ok(JsonFileStore { path, memory })
+    }
+
+    /// Writes all sessions to a temporary file and moves it over the old file
+    fn persist(&self) -> Result<(), Error> {
+        let sessions = self.memory
+            .sessions
+            .borrow()
+            .iter()
+            .map(|(key, session)| (key.to_string(), session.clone()))
+            .collect::<HashMap<String, HashMap<String, Value>>>();
+
+        let content = serde_json::to_string(&sessions).context(ErrorKind::JsonSerialize)?;
+
+        write_atomic(&self.path, &content)
+    }
+}
+
+/// Writes the content to a temporary file and moves it over the file at the path, so the file is
+/// never left half written
+pub fn write_atomic(path: &Path, content: &str) -> Result<(), Error> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+
+    {
+        let mut file = fs::File::create(&tmp_path).context(ErrorKind::IO)?;
+        file.write_all(content.as_bytes()).context(ErrorKind::IO)?;
+        file.sync_all().context(ErrorKind::IO)?;
+    }
+
+    fs::rename(&tmp_path, path).context(ErrorKind::IO)?;
+
+    Ok(())
+}
+
+impl SessionStore for JsonFileStore {
+    fn get(&self, key: SessionKey, name: &str) -> Result<Option<Value>, Error> {
+        self.memory.get(key, name)
+    }
+
+    fn set(&self, key: SessionKey, name: &str, value: Value) -> Result<(), Error> {
+        self.memory.set(key, name, value)?;
+        self.persist()
+    }
+
+    fn delete(&self, key: SessionKey, name: &str) -> Result<(), Error> {
+        self.memory.delete(key, name)?;
+        self.persist()
+    }
+
+    fn rename(&self, from: SessionKey, to: SessionKey) -> Result<(), Error> {
+        self.memory.rename(from, to)?;
+        self.persist()
+    }
+}
+
+/// Typed access to the session store of a bot
+#[derive(Clone)]
+pub struct Session {
+    store: Rc<SessionStore>,
+}
+
+impl Session {
+    pub fn new(store: Rc<SessionStore>) -> Session {
+        Session { store }
+    }
+
+    /// Returns the value with the given name, deserialized to the requested type
+    pub fn get<T: DeserializeOwned>(&self, key: SessionKey, name: &str) -> Result<Option<T>, Error> {
+        match self.store.get(key, name)? {
+            Some(value) => Ok(Some(serde_json::from_value(value).context(ErrorKind::JsonParse)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the value with the given name
+    pub fn set<T: Serialize>(&self, key: SessionKey, name: &str, value: &T) -> Result<(), Error> {
+        let value = serde_json::to_value(value).context(ErrorKind::JsonSerialize)?;
+
+        self.store.set(key, name, value)
+    }
+
+    /// Deletes the value with the given name
+    pub fn delete(&self, key: SessionKey, name: &str) -> Result<(), Error> {
+        self.store.delete(key, name)
+    }
+
+    /// Returns the underlying store
+    pub fn store(&self) -> &Rc<SessionStore> {
+        &self.store
+    }
+}
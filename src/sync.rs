@@ -0,0 +1,101 @@
+//! A bot handle which can be shared between threads
+//!
+//! `RcBot` is bound to the thread of its reactor. A `SyncBot` only holds the key and the name of
+//! the bot, it is `Send + Sync` and the futures of its functions are `Send`. The functions are
+//! inherent methods of `SyncBot`, so the `Function*` traits aren't needed:
+//!
+//! ```rust,ignore
+//! let sync_bot = bot.sync_handle();
+//!
+//! thread::spawn(move || {
+//!     let mut core = Core::new().unwrap();
+//!     let text = render_report();
+//!
+//!     core.run(sync_bot.message(CHANNEL_ID, text).send()).unwrap();
+//! });
+//! ```
+//!
+//! The futures have to run on a tokio runtime or a tokio-core reactor, since hyper spawns its
+//! connections on the default executor of tokio. A plain thread pool like `futures-cpupool`
+//! doesn't provide one and the requests fail there.
+//!
+//! The handlers and the polling of updates stay with the `RcBot`.
+
+use std::sync::{Arc, RwLock};
+
+use failure::Error;
+use futures::Future;
+use serde_json::Value;
+
+use bot::{request_formdata, request_json, RcBot};
+use file::File;
+
+pub struct SyncBotInner {
+    pub key: String,
+    pub name: RwLock<Option<String>>,
+}
+
+/// A clonable, thread safe bot which can only send requests
+#[derive(Clone)]
+pub struct SyncBot {
+    pub inner: Arc<SyncBotInner>,
+}
+
+impl SyncBot {
+    pub fn new(key: &str) -> SyncBot {
+        SyncBot {
+            inner: Arc::new(SyncBotInner {
+                key: key.into(),
+                name: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// Returns the username of the bot, if it was known when the handle was created
+    pub fn name(&self) -> Option<String> {
+        self.inner
+            .name
+            .read()
+            .ok()
+            .and_then(|name| name.clone())
+    }
+
+    /// Sets the username of the bot, e.g. after a call of `get_me`
+    pub fn set_name(&self, name: &str) {
+        if let Ok(mut current) = self.inner.name.write() {
+            *current = Some(name.into());
+        }
+    }
+
+    /// Sends a JSON request, see `Bot::fetch_json`
+    pub fn fetch_json(
+        &self,
+        func: &str,
+        msg: &str,
+    ) -> Box<Future<Item = String, Error = Error> + Send> {
+        Box::new(request_json(&self.inner.key, func, msg))
+    }
+
+    /// Sends a formdata request, see `Bot::fetch_formdata`
+    pub fn fetch_formdata(
+        &self,
+        func: &'static str,
+        msg: &Value,
+        file: File,
+        kind: &str,
+    ) -> Box<Future<Item = String, Error = Error> + Send> {
+        Box::new(request_formdata(&self.inner.key, func, msg, file, kind))
+    }
+}
+
+impl RcBot {
+    /// Creates a thread safe handle of the bot, which sends requests with the same key
+    pub fn sync_handle(&self) -> SyncBot {
+        let bot = SyncBot::new(&self.inner.key);
+        if let Some(ref name) = *self.inner.name.borrow() {
+            bot.set_name(name);
+        }
+
+        bot
+    }
+}
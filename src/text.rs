@@ -0,0 +1,51 @@
+//! Handlers for messages matching a regular expression
+//!
+//! Text handlers are registered with `RcBot::on_text` and checked in the order of registration.
+//! The first handler whose expression matches the text or caption of a message receives it
+//! together with the captured groups.
+
+use std::collections::HashMap;
+
+use regex;
+pub use regex::Regex;
+
+/// The capture groups of a match, owned so that they can be passed along with the message
+#[derive(Clone, Debug)]
+pub struct Captures {
+    groups: Vec<Option<String>>,
+    names: HashMap<String, usize>,
+}
+
+impl Captures {
+    pub fn new(regex: &Regex, caps: &regex::Captures) -> Captures {
+        let groups = (0..caps.len())
+            .map(|i| caps.get(i).map(|m| m.as_str().to_string()))
+            .collect();
+
+        let names = regex
+            .capture_names()
+            .enumerate()
+            .filter_map(|(i, name)| name.map(|name| (name.to_string(), i)))
+            .collect();
+
+        Captures { groups, names }
+    }
+
+    /// Returns the group with the given index, the whole match has the index 0
+    pub fn get(&self, i: usize) -> Option<&str> {
+        self.groups
+            .get(i)
+            .and_then(|group| group.as_ref())
+            .map(String::as_str)
+    }
+
+    /// Returns the group with the given name
+    pub fn name(&self, name: &str) -> Option<&str> {
+        self.names.get(name).and_then(|&i| self.get(i))
+    }
+
+    /// Returns the number of groups, including the whole match
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+}
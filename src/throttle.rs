@@ -0,0 +1,474 @@
+//! Rate limits for users and chats
+//!
+//! A throttle keeps a token bucket for every user and every chat. Each item takes one token from
+//! the bucket of its user and of its chat, the buckets refill continuously. A throttle can guard
+//! a single handler or, as middleware, the whole dispatcher:
+//!
+//! ```rust,ignore
+//! let throttle = Throttle::new(&bot)
+//!     .per_user(3, Duration::from_secs(10))?
+//!     .mode(ThrottleMode::Warn("Slow down, please".into()));
+//!
+//! let handle = bot.new_cmd("/search").throttle(throttle.clone()).and_then(...);
+//!
+//! // or for every update
+//! bot.add_middleware(throttle);
+//! ```
+//!
+//! Delayed items don't hold back the items of other users and chats. The middleware takes a
+//! delayed update out of the dispatch and runs it through the middleware and the handlers again
+//! once it may pass, the throttle itself lets it through then. An update which no handler takes
+//! at that point doesn't reach the stream of `get_stream` anymore.
+
+use std::{rc::Rc, cell::{Cell, RefCell}, collections::{HashMap, HashSet},
+          time::{Duration, Instant}};
+
+use failure::{Error, Fail};
+use futures::{future, Future, Stream};
+use futures::sync::mpsc::{self, UnboundedSender};
+use tokio_core::reactor::Timeout;
+
+use bot::RcBot;
+use error::ErrorKind;
+use filter::Filterable;
+use functions::FunctionMessage;
+use handler::HandlerStream;
+use middleware::Middleware;
+use objects::{Integer, Update};
+use queue::ChatItem;
+use session::SessionKey;
+
+/// How often buckets which are full again are removed
+const EVICT_INTERVAL: u64 = 60;
+
+/// What happens to an item which exceeds the rate limit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// The item is dropped silently
+    Drop,
+    /// The item is passed on as soon as a token is available. At most `capacity` items of a user
+    /// or chat wait at once, further items are dropped.
+    Delay,
+    /// The item is dropped, the first dropped item of a user is answered with the text
+    Warn(String),
+}
+
+/// Allows `capacity` items at once, which are refilled over `period`
+#[derive(Clone, Copy, Debug)]
+struct Rate {
+    capacity: u32,
+    period: Duration,
+}
+
+impl Rate {
+    fn new(capacity: u32, period: Duration) -> Result<Rate, Error> {
+        if capacity == 0 || period == Duration::from_secs(0) {
+            return Err(Error::from(ErrorKind::InvalidRate));
+        }
+
+        Ok(Rate { capacity, period })
+    }
+
+    fn tokens_per_sec(&self) -> f64 {
+        let period = self.period.as_secs() as f64 + f64::from(self.period.subsec_nanos()) / 1e9;
+
+        f64::from(self.capacity) / period
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: Rate) -> Bucket {
+        Bucket {
+            tokens: f64::from(rate.capacity),
+            updated: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket up to the capacity of the rate
+    fn refill(&mut self, rate: Rate) {
+        let elapsed = self.updated.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+
+        self.tokens = (self.tokens + elapsed * rate.tokens_per_sec())
+            .min(f64::from(rate.capacity));
+        self.updated = Instant::now();
+    }
+
+    /// The time until the next token is available, the tokens are negative while delayed items
+    /// wait for theirs
+    fn wait_time(&self, rate: Rate) -> Duration {
+        let missing = (1.0 - self.tokens).max(0.0) / rate.tokens_per_sec();
+
+        Duration::new(missing.trunc() as u64, (missing.fract() * 1e9) as u32)
+    }
+}
+
+struct ThrottleInner {
+    bot: RcBot,
+    per_user: Cell<Option<Rate>>,
+    per_chat: Cell<Option<Rate>>,
+    mode: RefCell<ThrottleMode>,
+    buckets: RefCell<HashMap<SessionKey, Bucket>>,
+    warned: RefCell<HashSet<SessionKey>>,
+    evicted: Cell<Instant>,
+}
+
+/// Marks an update which was delayed by the throttle with the given address, so that it passes
+/// the throttle when it is dispatched again
+struct Delayed(usize);
+
+/// Token buckets for users and chats, cloning the throttle shares the buckets
+#[derive(Clone)]
+pub struct Throttle {
+    inner: Rc<ThrottleInner>,
+}
+
+impl Throttle {
+    /// Creates a throttle without limits, which drops items exceeding a limit
+    pub fn new(bot: &RcBot) -> Throttle {
+        Throttle {
+            inner: Rc::new(ThrottleInner {
+                bot: bot.clone(),
+                per_user: Cell::new(None),
+                per_chat: Cell::new(None),
+                mode: RefCell::new(ThrottleMode::Drop),
+                buckets: RefCell::new(HashMap::new()),
+                warned: RefCell::new(HashSet::new()),
+                evicted: Cell::new(Instant::now()),
+            }),
+        }
+    }
+
+    /// Allows each user `capacity` items, which are refilled over the period. Fails if the
+    /// capacity or the period is zero.
+    pub fn per_user(self, capacity: u32, period: Duration) -> Result<Self, Error> {
+        self.inner.per_user.set(Some(Rate::new(capacity, period)?));
+
+        Ok(self)
+    }
+
+    /// Allows each chat `capacity` items, which are refilled over the period. Fails if the
+    /// capacity or the period is zero.
+    pub fn per_chat(self, capacity: u32, period: Duration) -> Result<Self, Error> {
+        self.inner.per_chat.set(Some(Rate::new(capacity, period)?));
+
+        Ok(self)
+    }
+
+    /// Sets what happens to items which exceed a limit
+    pub fn mode(self, mode: ThrottleMode) -> Self {
+        *self.inner.mode.borrow_mut() = mode;
+
+        self
+    }
+
+    fn rate(&self, key: SessionKey) -> Option<Rate> {
+        match key {
+            SessionKey::User(_) => self.inner.per_user.get(),
+            SessionKey::Chat(_) => self.inner.per_chat.get(),
+        }
+    }
+
+    /// Checks whether the user or chat has no tokens left
+    pub fn is_throttled(&self, key: SessionKey) -> bool {
+        let rate = match self.rate(key) {
+            Some(rate) => rate,
+            None => return false,
+        };
+
+        match self.inner.buckets.borrow_mut().get_mut(&key) {
+            Some(bucket) => {
+                bucket.refill(rate);
+                bucket.tokens < 1.0
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the users and chats which have no tokens left
+    pub fn throttled(&self) -> Vec<SessionKey> {
+        let keys = self.inner.buckets.borrow().keys().cloned().collect::<Vec<_>>();
+
+        keys.into_iter()
+            .filter(|key| self.is_throttled(*key))
+            .collect()
+    }
+
+    /// The limited user and chat of an item together with their rates
+    fn keys(&self, chat_id: Option<Integer>, user_id: Option<Integer>) -> Vec<(SessionKey, Rate)> {
+        user_id
+            .map(SessionKey::User)
+            .into_iter()
+            .chain(chat_id.map(SessionKey::Chat))
+            .filter_map(|key| self.rate(key).map(|rate| (key, rate)))
+            .collect()
+    }
+
+    /// Takes a token from the buckets of the user and the chat. If one of them is empty, nothing
+    /// is taken and the time until both have a token is returned.
+    pub fn acquire(
+        &self,
+        chat_id: Option<Integer>,
+        user_id: Option<Integer>,
+    ) -> Result<(), Duration> {
+        let keys = self.keys(chat_id, user_id);
+
+        let mut buckets = self.inner.buckets.borrow_mut();
+        self.evict(&mut buckets);
+
+        let mut wait = Duration::from_secs(0);
+        for &(key, rate) in &keys {
+            let bucket = buckets.entry(key).or_insert_with(|| Bucket::new(rate));
+
+            bucket.refill(rate);
+            wait = wait.max(bucket.wait_time(rate));
+        }
+
+        if wait > Duration::from_secs(0) {
+            return Err(wait);
+        }
+
+        for &(key, _) in &keys {
+            if let Some(bucket) = buckets.get_mut(&key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        if let Some(user_id) = user_id {
+            self.inner.warned.borrow_mut().remove(&SessionKey::User(user_id));
+        }
+
+        Ok(())
+    }
+
+    /// Removes the buckets which are full again, they don't differ from new buckets. Runs at
+    /// most once per interval.
+    fn evict(&self, buckets: &mut HashMap<SessionKey, Bucket>) {
+        if self.inner.evicted.get().elapsed() < Duration::from_secs(EVICT_INTERVAL) {
+            return;
+        }
+
+        buckets.retain(|key, bucket| match self.rate(*key) {
+            Some(rate) => {
+                bucket.refill(rate);
+                bucket.tokens < f64::from(rate.capacity)
+            }
+            None => false,
+        });
+
+        // a user whose bucket was removed passes again, so the next limit is warned again
+        self.inner
+            .warned
+            .borrow_mut()
+            .retain(|key| buckets.contains_key(key));
+
+        self.inner.evicted.set(Instant::now());
+    }
+
+    /// Takes a token from the buckets of the user and the chat even if they are empty and
+    /// returns the time until the token is paid back. Returns None without taking a token if the
+    /// item would wait longer than a period, i.e. if `capacity` items wait already.
+    fn reserve(&self, chat_id: Option<Integer>, user_id: Option<Integer>) -> Option<Duration> {
+        let keys = self.keys(chat_id, user_id);
+
+        let mut buckets = self.inner.buckets.borrow_mut();
+        self.evict(&mut buckets);
+
+        let mut wait = Duration::from_secs(0);
+        let mut limit = Duration::from_secs(0);
+        for &(key, rate) in &keys {
+            let bucket = buckets.entry(key).or_insert_with(|| Bucket::new(rate));
+
+            bucket.refill(rate);
+            wait = wait.max(bucket.wait_time(rate));
+            limit = limit.max(rate.period);
+        }
+
+        if wait > limit {
+            return None;
+        }
+
+        for &(key, _) in &keys {
+            if let Some(bucket) = buckets.get_mut(&key) {
+                bucket.tokens -= 1.0;
+            }
+        }
+
+        Some(wait)
+    }
+
+    /// Returns how long the item has to wait until it may pass, or None if it is dropped
+    fn admit(
+        &self,
+        chat_id: Option<Integer>,
+        user_id: Option<Integer>,
+        message_id: Option<Integer>,
+    ) -> Option<Duration> {
+        let mode = self.inner.mode.borrow().clone();
+
+        if mode == ThrottleMode::Delay {
+            let wait = self.reserve(chat_id, user_id);
+            if wait != Some(Duration::from_secs(0)) {
+                debug!("Throttled user {:?} in chat {:?}", user_id, chat_id);
+            }
+
+            return wait;
+        }
+
+        if self.acquire(chat_id, user_id).is_ok() {
+            return Some(Duration::from_secs(0));
+        }
+
+        debug!("Throttled user {:?} in chat {:?}", user_id, chat_id);
+
+        if let ThrottleMode::Warn(text) = mode {
+            if let (Some(chat_id), Some(user_id)) = (chat_id, user_id) {
+                if self.inner.warned.borrow_mut().insert(SessionKey::User(user_id)) {
+                    self.warn(chat_id, message_id, text);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn warn(&self, chat_id: Integer, message_id: Option<Integer>, text: String) {
+        let mut msg = self.inner.bot.message(chat_id, text);
+        if let Some(id) = message_id {
+            msg = msg.reply_to_message_id(id);
+        }
+
+        self.inner.bot.inner.handle.spawn(
+            msg.send()
+                .map(|_| ())
+                .map_err(|e| error!("Couldn't send the cooldown warning: {}", e)),
+        );
+    }
+
+    /// Resolves after the delay of an item
+    fn timer(&self, wait: Duration) -> Box<Future<Item = (), Error = Error>> {
+        let timeout = Timeout::new(wait, &self.inner.bot.inner.handle);
+
+        Box::new(
+            future::result(timeout)
+                .and_then(|timeout| timeout)
+                .map_err(|e| Error::from(e.context(ErrorKind::Timer))),
+        )
+    }
+
+    /// The address of the shared state, which tells throttles apart
+    fn id(&self) -> usize {
+        &*self.inner as *const ThrottleInner as usize
+    }
+
+    /// Dispatches a delayed update again once it may pass
+    fn resubmit(&self, wait: Duration, mut update: Update) {
+        update.extensions.insert(Delayed(self.id()));
+
+        let bot = self.inner.bot.clone();
+        let fut = self.timer(wait)
+            .and_then(move |_| bot.process(update))
+            .map(|rest| {
+                if let Some((_, update)) = rest {
+                    debug!("The delayed update {} wasn't handled", update.update_id);
+                }
+            })
+            .map_err(|e| error!("Couldn't dispatch a delayed update: {}", e));
+
+        self.inner.bot.inner.handle.spawn(fut);
+    }
+
+    /// Passes a delayed item of a handler on once it may pass
+    fn send_later<T: 'static>(
+        &self,
+        wait: Duration,
+        item: T,
+        sender: UnboundedSender<Result<T, Error>>,
+    ) {
+        let fut = self.timer(wait).then(move |result| {
+            // the handler may have been dropped in the meantime
+            let _ = sender.unbounded_send(result.map(|_| item));
+
+            Ok::<(), ()>(())
+        });
+
+        self.inner.bot.inner.handle.spawn(fut);
+    }
+}
+
+impl Middleware for Throttle {
+    fn before(
+        &self,
+        _bot: &RcBot,
+        mut update: Update,
+    ) -> Box<Future<Item = Option<Update>, Error = Error>> {
+        // the token of a delayed update was taken before
+        if let Some(Delayed(id)) = update.extensions.remove::<Delayed>() {
+            if id == self.id() {
+                return Box::new(future::ok(Some(update)));
+            }
+        }
+
+        let chat_id = update.message().map(|msg| msg.chat.id);
+        let user_id = update.sender().map(|user| user.id);
+        let message_id = update.message().map(|msg| msg.message_id);
+
+        match self.admit(chat_id, user_id, message_id) {
+            Some(wait) if wait == Duration::from_secs(0) => Box::new(future::ok(Some(update))),
+            Some(wait) => {
+                self.resubmit(wait, update);
+
+                Box::new(future::ok(None))
+            }
+            None => Box::new(future::ok(None)),
+        }
+    }
+}
+
+impl<T> HandlerStream<T>
+where
+    T: ChatItem + 'static,
+{
+    /// Limits the items of the handler with the throttle. Delayed items are passed on once they
+    /// may pass, the items of other users and chats don't wait for them.
+    pub fn throttle(self, throttle: Throttle) -> HandlerStream<T> {
+        let handle = self.handle();
+        let reactor = throttle.inner.bot.inner.handle.clone();
+        let (sender, receiver) = mpsc::unbounded();
+
+        // the forwarding ends once the handler is dropped
+        let forward = self.then(move |result| {
+            let item = match result {
+                Ok(item) => item,
+                Err(e) => return sender.unbounded_send(Err(e)).map_err(|_| ()),
+            };
+
+            let origin = item.origin();
+            match throttle.admit(origin.chat_id, origin.user_id, origin.message_id) {
+                Some(wait) if wait == Duration::from_secs(0) => {
+                    sender.unbounded_send(Ok(item)).map_err(|_| ())
+                }
+                Some(wait) => {
+                    throttle.send_later(wait, item, sender.clone());
+
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        });
+
+        reactor.spawn(forward.for_each(|_| Ok(())));
+
+        let stream = receiver.then(|result| match result {
+            Ok(item) => item,
+            Err(()) => Err(Error::from(ErrorKind::Channel)),
+        });
+
+        HandlerStream::new(stream, handle)
+    }
+}
@@ -0,0 +1,131 @@
+//! Waiting for the next message of a user
+//!
+//! `RcBot::wait_for_message` resolves to the next message which a user sends in a chat. The
+//! message is taken out of the normal dispatch, so it doesn't trigger commands or other handlers:
+//!
+//! ```rust,ignore
+//! let rename = bot.new_cmd("/rename");
+//!
+//! bot.register_per_chat(rename, |(bot, msg)| {
+//!     let user_id = msg.from.as_ref().map(|user| user.id).unwrap_or(0);
+//!
+//!     bot.message(msg.chat.id, "What should the new name be?".into()).send()
+//!         .and_then(move |(bot, _)| {
+//!             bot.wait_for_message(msg.chat.id, user_id, Duration::from_secs(60))
+//!                 .map(move |answer| (bot, msg, answer))
+//!         })
+//!         .and_then(|(bot, msg, answer)| match answer.and_then(|answer| answer.text) {
+//!             Some(name) => bot.message(msg.chat.id, format!("Renamed to {}", name)).send(),
+//!             None => bot.message(msg.chat.id, "Never mind".into()).send(),
+//!         })
+//! });
+//! ```
+//!
+//! A handler which waits doesn't finish until the answer arrives or the wait times out. The
+//! items of a handler stream are processed one after another, so chaining the wait with
+//! `and_then` onto the stream of `new_cmd` would block the command for every other chat in the
+//! meantime. Handlers registered with `register_per_chat` only block the chat of the wait.
+
+use std::{rc::Rc, cell::{Cell, RefCell}, collections::HashMap};
+
+use futures::sync::oneshot;
+
+use bot::{Interceptor, RcBot};
+use objects::{Integer, Message};
+
+struct WaitersInner {
+    next_id: Cell<usize>,
+    waiting: RefCell<HashMap<(Integer, Integer), (usize, oneshot::Sender<Message>)>>,
+}
+
+/// The users whose next message is expected, cloning shares the waiters. Every user can be
+/// awaited only once per chat, a newer wait replaces the older one.
+#[derive(Clone)]
+pub struct Waiters {
+    inner: Rc<WaitersInner>,
+}
+
+impl Waiters {
+    pub fn new() -> Waiters {
+        Waiters {
+            inner: Rc::new(WaitersInner {
+                next_id: Cell::new(0),
+                waiting: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Waits for the next message of the user in the chat, returns the id of the wait and the
+    /// receiver of the message. The receiver of a replaced wait is canceled.
+    pub fn add(&self, chat_id: Integer, user_id: Integer) -> (usize, oneshot::Receiver<Message>) {
+        let id = self.inner.next_id.get();
+        self.inner.next_id.set(id + 1);
+
+        let (sender, receiver) = oneshot::channel();
+        self.inner
+            .waiting
+            .borrow_mut()
+            .insert((chat_id, user_id), (id, sender));
+
+        (id, receiver)
+    }
+
+    /// Removes the wait with the id, e.g. after a timeout. Returns false if it was already
+    /// finished or replaced.
+    pub fn remove(&self, chat_id: Integer, user_id: Integer, id: usize) -> bool {
+        let mut waiting = self.inner.waiting.borrow_mut();
+
+        match waiting.get(&(chat_id, user_id)) {
+            Some(&(current, _)) if current == id => {}
+            _ => return false,
+        }
+
+        waiting.remove(&(chat_id, user_id));
+
+        true
+    }
+
+    /// Returns true if the next message of the user in the chat is awaited
+    pub fn is_waiting(&self, chat_id: Integer, user_id: Integer) -> bool {
+        self.inner.waiting.borrow().contains_key(&(chat_id, user_id))
+    }
+}
+
+impl Default for Waiters {
+    fn default() -> Waiters {
+        Waiters::new()
+    }
+}
+
+impl Interceptor for Waiters {
+    fn intercept(&self, _bot: &RcBot, msg: Message) -> Option<Message> {
+        let user_id = match msg.from {
+            Some(ref user) => user.id,
+            None => return Some(msg),
+        };
+
+        let waiter = self.inner.waiting.borrow_mut().remove(&(msg.chat.id, user_id));
+
+        match waiter {
+            // the receiver is gone if the waiting future was dropped
+            Some((_, sender)) => sender.send(msg).err(),
+            None => Some(msg),
+        }
+    }
+
+    fn migrate_chat(&self, from: Integer, to: Integer) {
+        let mut waiting = self.inner.waiting.borrow_mut();
+
+        let keys = waiting
+            .keys()
+            .filter(|&&(chat_id, _)| chat_id == from)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for (chat_id, user_id) in keys {
+            if let Some(waiter) = waiting.remove(&(chat_id, user_id)) {
+                waiting.insert((to, user_id), waiter);
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
     #![feature(try_from)]
     #![feature(proc_macro, proc_macro_lib)]
-    #![recursion_limit="150"]
+    #![recursion_limit="256"]
 
     extern crate log;
     extern crate proc_macro;
@@ -221,10 +221,46 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
         }
     };
 
+    // the same function for the thread safe bot, its future is Send
+    let sync_wrapper_name = syn::Ident::from(format!("SyncWrapper{}", name.as_ref()));
+    let field_compulsory4 = field_compulsory.clone();
+    let ty_compulsory3 = ty_compulsory.clone();
+    let field_compulsory5 = field_compulsory2.clone();
+    let values2 = values.clone();
+    let field_optional3 = field_optional.clone();
+    let field_optional4 = field_optional.clone();
+    let ty_optional2 = ty_optional.clone();
+
+    let sync_tokens = quote! {
+        #[allow(dead_code)]
+        pub struct #sync_wrapper_name {
+            bot: SyncBot,
+            inner: #name,
+            file: Option<Result<file::File, Error>>
+        }
+
+        impl SyncBot {
+            pub fn #bot_function(&self, #( #field_compulsory4: #ty_compulsory3, )*) -> #sync_wrapper_name {
+                #sync_wrapper_name { inner: #name { #( #field_compulsory5: #values2, )* }, bot: self.clone(), file: None }
+            }
+        }
+
+        impl #sync_wrapper_name {
+            #(
+                pub fn #field_optional3<S>(mut self, val: S) -> Self where S: Into<#ty_optional2> {
+                    self.inner.#field_optional4 = Some(val.into());
+
+                    self
+                }
+            )*
+        }
+    };
+
     if let Some(file_kind) = file_kind {
         let file_kind_name = syn::Lit::Str(format!("{}", file_kind), syn::StrStyle::Cooked);
         quote! {
             #tokens
+            #sync_tokens
 
             pub trait #trait_name {
                  fn #bot_function(&self, #( #field_compulsory: #ty_compulsory, )*) -> #wrapper_name;
@@ -315,10 +351,53 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
                     }
                 }
             }
+
+            impl #sync_wrapper_name {
+                pub fn send(self) -> Box<Future<Item=(SyncBot, objects::#answer), Error=Error> + Send> {
+                    use futures::future;
+
+                    let msg = match serde_json::to_value(&self.inner) {
+                        Ok(msg) => msg,
+                        Err(err) => return Box::new(future::err(Error::from(err.context(ErrorKind::JsonSerialize))))
+                    };
+
+                    let bot = self.bot;
+                    let request = match self.file {
+                        Some(Ok(file)) => bot.fetch_formdata(#function, &msg, file, #file_kind_name),
+                        Some(Err(e)) => return Box::new(future::err(e)),
+                        None => bot.fetch_json(#function, &msg.to_string())
+                    };
+
+                    Box::new(request.and_then(move |answer| {
+                        serde_json::from_str::<objects::#answer>(&answer)
+                            .map(|json| (bot, json))
+                            .map_err(|x| Error::from(x.context(ErrorKind::JsonParse)))
+                    }))
+                }
+
+                pub fn url<S>(mut self, val: S) -> Self where S: Into<String> {
+                    self.inner.#file_kind = Some(val.into());
+
+                    self
+                }
+
+                pub fn file_id<S>(mut self, val: S) -> Self where S: Into<String> {
+                    self.inner.#file_kind = Some(val.into());
+
+                    self
+                }
+
+                pub fn file<S>(mut self, val: S) -> Self where S: TryInto<file::File> {
+                    self.file = Some(val.try_into().map_err(|_| Error::from(ErrorKind::NoFile)));
+
+                    self
+                }
+            }
         }
     } else {
         quote! {
             #tokens
+            #sync_tokens
 
             pub trait #trait_name {
                  fn #bot_function(&self, #( #field_compulsory: #ty_compulsory, )*) -> #wrapper_name;
@@ -358,6 +437,24 @@ fn expand_function(ast: syn::MacroInput) -> quote::Tokens {
                     }
                 )*
             }
+
+            impl #sync_wrapper_name {
+                pub fn send(self) -> Box<Future<Item=(SyncBot, objects::#answer), Error=Error> + Send> {
+                    use futures::future;
+
+                    let msg = match serde_json::to_string(&self.inner) {
+                        Ok(msg) => msg,
+                        Err(err) => return Box::new(future::err(Error::from(err.context(ErrorKind::JsonSerialize))))
+                    };
+
+                    let bot = self.bot;
+                    Box::new(bot.fetch_json(#function, &msg).and_then(move |answer| {
+                        serde_json::from_str::<objects::#answer>(&answer)
+                            .map(|json| (bot, json))
+                            .map_err(|x| Error::from(x.context(ErrorKind::JsonParse)))
+                    }))
+                }
+            }
         }
     }
 }
@@ -377,3 +474,230 @@ fn config_from(attrs: &[syn::Attribute]) -> BTreeMap<String, String> {
     result
 }
 
+
+#[proc_macro_derive(BotCommands, attributes(command, rest))]
+pub fn derive_bot_commands(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input(&input.to_string()).unwrap();
+    let expanded = expand_bot_commands(ast);
+    expanded.to_string().parse().unwrap()
+}
+
+fn expand_bot_commands(ast: syn::MacroInput) -> quote::Tokens {
+    let variants = match ast.body {
+        syn::Body::Enum(ref variants) => variants,
+        _ => panic!("#[derive(BotCommands)] can only be used with enums"),
+    };
+
+    let name = &ast.ident;
+
+    let mut names = Vec::new();
+    let mut descriptions = Vec::new();
+    let mut arms = Vec::new();
+    let mut help = doc_from(&ast.attrs).map(|doc| format!("{}\n\n", doc)).unwrap_or_default();
+
+    for variant in variants {
+        let variant_name = &variant.ident;
+        let command = command_name(variant);
+        let description = doc_from(&variant.attrs).unwrap_or_default();
+
+        let fields: Vec<&syn::Field> = match variant.data {
+            syn::VariantData::Struct(ref fields) | syn::VariantData::Tuple(ref fields) => {
+                fields.iter().collect()
+            },
+            syn::VariantData::Unit => vec![],
+        };
+
+        // build the usage line, e.g. "/location <latitude> <longitude> [zoom]"
+        let mut usage = command.clone();
+        for (i, field) in fields.iter().enumerate() {
+            let arg_name = field_name(field, i);
+            let optional = option_inner(&field.ty).is_some();
+
+            usage.push_str(&match (is_rest(field), optional) {
+                (true, true) => format!(" [{}...]", arg_name),
+                (true, false) => format!(" <{}...>", arg_name),
+                (false, true) => format!(" [{}]", arg_name),
+                (false, false) => format!(" <{}>", arg_name),
+            });
+        }
+
+        if description.is_empty() {
+            help.push_str(&format!("{}\n", usage));
+        } else {
+            help.push_str(&format!("{} - {}\n", usage, description));
+        }
+
+        let command_lit = syn::Lit::Str(command.clone(), syn::StrStyle::Cooked);
+        let usage_lit = syn::Lit::Str(usage, syn::StrStyle::Cooked);
+
+        // parse each field from the arguments and bind it to __arg<i>
+        let bindings: Vec<_> = (0..fields.len())
+            .map(|i| syn::Ident::from(format!("__arg{}", i)))
+            .collect();
+
+        let parsers: Vec<_> = fields.iter().enumerate().map(|(i, field)| {
+            let binding = &bindings[i];
+            let arg_name = syn::Lit::Str(field_name(field, i), syn::StrStyle::Cooked);
+
+            let parse = match (is_rest(field), option_inner(&field.ty)) {
+                (true, Some(_)) => quote! { Ok::<_, String>(args.rest()) },
+                (true, None) => quote! { args.required_rest(#arg_name) },
+                (false, Some(inner)) => quote! { args.optional::<#inner>(#arg_name) },
+                (false, None) => {
+                    let ty = &field.ty;
+                    quote! { args.required::<#ty>(#arg_name) }
+                },
+            };
+
+            quote! {
+                let #binding = #parse.map_err(|msg| {
+                    ::telebot::error::UsageError::new(#command_lit, #usage_lit, msg)
+                })?;
+            }
+        }).collect();
+
+        let construct = match variant.data {
+            syn::VariantData::Struct(ref fields) => {
+                let field_names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+                let bindings = bindings.clone();
+
+                quote! { #name::#variant_name { #( #field_names: #bindings, )* } }
+            },
+            syn::VariantData::Tuple(_) => {
+                let bindings = bindings.clone();
+
+                quote! { #name::#variant_name( #( #bindings, )* ) }
+            },
+            syn::VariantData::Unit => quote! { #name::#variant_name },
+        };
+
+        arms.push(quote! {
+            #command_lit => {
+                #[allow(unused_mut)]
+                let mut args = ::telebot::command::Args::new(&command.args);
+
+                #( #parsers )*
+
+                args.finish().map_err(|msg| {
+                    ::telebot::error::UsageError::new(#command_lit, #usage_lit, msg)
+                })?;
+
+                Ok(#construct)
+            }
+        });
+
+        names.push(command_lit);
+        descriptions.push(syn::Lit::Str(description, syn::StrStyle::Cooked));
+    }
+
+    let names2 = names.clone();
+    let names3 = names.clone();
+    let help = syn::Lit::Str(help.trim_right().to_string(), syn::StrStyle::Cooked);
+
+    quote! {
+        impl ::telebot::command::BotCommands for #name {
+            fn parse(command: &::telebot::command::Command) -> Result<Self, ::telebot::error::UsageError> {
+                match command.name.as_str() {
+                    #( #arms )*
+                    _ => Err(::telebot::error::UsageError::new(&command.name, "", "Unknown command")),
+                }
+            }
+
+            fn names() -> Vec<&'static str> {
+                vec![ #( #names2, )* ]
+            }
+
+            fn descriptions() -> Vec<(&'static str, &'static str)> {
+                vec![ #( (#names3, #descriptions), )* ]
+            }
+
+            fn help() -> String {
+                #help.into()
+            }
+        }
+    }
+}
+
+/// Returns the command of a variant, either set with #[command = "name"] or the variant name in
+/// snake case
+fn command_name(variant: &syn::Variant) -> String {
+    for attr in &variant.attrs {
+        if let syn::MetaItem::NameValue(ref name, syn::Lit::Str(ref value, _)) = attr.value {
+            if name.as_ref() == "command" {
+                return if value.starts_with("/") { value.clone() } else { format!("/{}", value) };
+            }
+        }
+    }
+
+    let mut command = String::from("/");
+    for (i, c) in variant.ident.as_ref().chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                command.push('_');
+            }
+            command.extend(c.to_lowercase());
+        } else {
+            command.push(c);
+        }
+    }
+
+    command
+}
+
+/// Joins the doc comments of an item to a single line
+fn doc_from(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs.iter().filter_map(|attr| {
+        if let syn::MetaItem::NameValue(ref name, syn::Lit::Str(ref value, _)) = attr.value {
+            if name.as_ref() == "doc" {
+                return Some(value.trim_left_matches("///").trim().to_string());
+            }
+        }
+
+        None
+    }).filter(|line| !line.is_empty()).collect();
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join(" "))
+    }
+}
+
+/// Checks whether a field is marked with #[rest]
+fn is_rest(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|attr| match attr.value {
+        syn::MetaItem::Word(ref name) => name.as_ref() == "rest",
+        _ => false,
+    })
+}
+
+/// Returns the name of a field as it is shown in the usage
+fn field_name(field: &syn::Field, idx: usize) -> String {
+    match field.ident {
+        Some(ref ident) => ident.as_ref().to_string(),
+        None => match option_inner(&field.ty) {
+            Some(inner) => quote!(#inner).to_string().replace(" ", ""),
+            None => {
+                let ty = &field.ty;
+                let name = quote!(#ty).to_string().replace(" ", "");
+
+                if name.is_empty() { format!("arg{}", idx) } else { name }
+            },
+        },
+    }
+}
+
+/// Returns the inner type if the type is an Option
+fn option_inner(ty: &syn::Ty) -> Option<syn::Ty> {
+    if let syn::Ty::Path(_, ref path) = *ty {
+        if let Some(segment) = path.segments.last() {
+            if segment.ident.as_ref() == "Option" {
+                if let syn::PathParameters::AngleBracketed(ref param) = segment.parameters {
+                    return param.types.first().cloned();
+                }
+            }
+        }
+    }
+
+    None
+}